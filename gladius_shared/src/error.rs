@@ -1,3 +1,4 @@
+use crate::types::Vertex;
 use serde::{Deserialize, Serialize};
 
 ///Errors that can be generated during the slicing process
@@ -28,16 +29,37 @@ pub enum SlicerErrors {
     },
 
     ///Error loading the STL
-    StlLoadError,
+    StlLoadError {
+        ///File that failed to load
+        filepath: String,
+        ///Byte offset of the incomplete data in a binary file
+        offset: Option<usize>,
+        ///Line of the invalid data in an ascii file
+        line: Option<usize>,
+    },
 
     ///Error Loading the 3MF file
-    ThreemfLoadError,
+    ThreemfLoadError {
+        ///File that failed to load
+        filepath: String,
+        ///The XML element or archive part that could not be loaded
+        element: String,
+        ///Why the element could not be loaded
+        reason: String,
+    },
 
     /// Error loading the 3MF file, usally a compatiblity error
     ThreemfUnsupportedType,
 
     ///Error during tower generation
-    TowerGeneration,
+    TowerGeneration {
+        ///Height the ring failed to close at
+        height: f64,
+        ///Indices of the triangles in the rings that failed to close
+        triangles: Vec<usize>,
+        ///The points at the open ends of the rings
+        points: Vec<Vertex>,
+    },
 
     ///No input models provided
     NoInputProvided,
@@ -60,8 +82,15 @@ pub enum SlicerErrors {
         filepath: String,
     },
 
-    ///Error during tower generation
-    SliceGeneration,
+    ///Error ordering the polygons of a slice
+    SliceGeneration {
+        ///The layer index
+        layer: usize,
+        ///The height of the layer
+        height: f64,
+        ///The points of the loop that is not inside any polygon
+        points: Vec<Vertex>,
+    },
 
     ///File permission issue will settings file or folder
     SettingsFilePermission,
@@ -96,12 +125,78 @@ pub enum SlicerErrors {
         value: f64,
     },
 
+    ///Error loading the OBJ file
+    ObjLoadError {
+        ///File that failed to load
+        filepath: String,
+        ///Line of the invalid data
+        line: usize,
+    },
+
+    ///Error loading the AMF file
+    AmfLoadError {
+        ///File that failed to load
+        filepath: String,
+        ///The XML element or archive part that could not be loaded
+        element: String,
+        ///Why the element could not be loaded
+        reason: String,
+    },
+
+    ///Error loading the PLY file
+    PlyLoadError {
+        ///File that failed to load
+        filepath: String,
+        ///Byte offset of the invalid data
+        offset: usize,
+    },
+
+    ///The models could not be arranged on the build plate
+    ModelsDoNotFitBuildArea {
+        ///The number of models being arranged
+        objects: usize,
+        ///The width in x needed to fit the models in mm
+        required_x: f64,
+        ///The depth in y needed to fit the models in mm
+        required_y: f64,
+    },
+
+    ///The face selected to lay flat is not in the model
+    LayFlatFaceNotFound {
+        ///File the face was selected from
+        filepath: String,
+        ///The selected triangle index
+        face: usize,
+        ///The number of triangles in the file
+        faces: usize,
+    },
+
     ///The file format is not supported
     FileFormatNotSupported {
         /// File with invalid Format
         filepath: String,
     },
 
+    ///The server could not listen for requests
+    ServerConnectionError {
+        ///The address the server tried to use
+        address: String,
+    },
+
+    ///A slice was requested before any models were loaded
+    NoModelsLoaded,
+
+    ///The slice was cancelled before it finished
+    Cancelled,
+
+    ///The face selected to lay flat has no area so no normal
+    LayFlatFaceDegenerate {
+        ///File the face was selected from
+        filepath: String,
+        ///The selected triangle index
+        face: usize,
+    },
+
     ///Another error, here for plugins to use
     UnspecifiedError(String),
 }
@@ -119,11 +214,16 @@ impl SlicerErrors {
             SlicerErrors::SettingsFileNotFound {filepath} => {
                 (0x1001,format!("Could not load settings file \"{}\". It was not found in the filesystem. Please check that the file exists and retry.",filepath))
             }
-            SlicerErrors::StlLoadError => {
-                (0x1002,"There was a issue loading the STL file.".to_string())
+            SlicerErrors::StlLoadError { filepath, offset, line } => {
+                let location = match (offset, line) {
+                    (Some(offset), _) => format!(" at byte {}", offset),
+                    (None, Some(line)) => format!(" at line {}", line),
+                    (None, None) => String::new(),
+                };
+                (0x1002,format!("There was a issue loading the STL file \"{}\"{}.",filepath, location))
             }
-            SlicerErrors::ThreemfLoadError => {
-                (0x1003,"There was a issue loading the 3MF file. This file format is still in development. Please report this issue to github.".to_string())
+            SlicerErrors::ThreemfLoadError { filepath, element, reason } => {
+                (0x1003,format!("There was a issue loading the 3MF file \"{}\" at {}: {}. This file format is still in development. Please report this issue to github.",filepath, element, reason))
             }
             SlicerErrors::ThreemfUnsupportedType => {
                 (0x1004,"There was a issue loading the 3MF file. This file is unsupported by our zip reader dependency. Work is going towards upgrading support for these files.".to_string())
@@ -134,8 +234,8 @@ impl SlicerErrors {
             SlicerErrors::SettingsFileMissingSettings { missing_setting } => {
                 (0x1006,format!("Could not load settings file. Was missing settings {}.",missing_setting))
             }
-            SlicerErrors::TowerGeneration  => {
-                (0x1007,"Error Creating Tower. Model most likely needs repair. Please Repair and run again.".to_string())
+            SlicerErrors::TowerGeneration { height, triangles, .. } => {
+                (0x1007,format!("Error Creating Tower at height {:.3} mm where {} triangles do not form a closed loop. Model most likely needs repair. Please Repair and run again.",height, triangles.len()))
             }
             SlicerErrors::NoInputProvided  => {
                 (0x1008,"No Input Provided.".to_string())
@@ -146,8 +246,8 @@ impl SlicerErrors {
             SlicerErrors::SettingsRecursiveLoadError { filepath } => {
                 (0x100A,format!("Failed to load addional settings file {}",filepath))
             }
-            SlicerErrors::SliceGeneration => {
-                (0x100B,"There was a issue ordering the polygon for slicing. Try repairing your Model.".to_string())
+            SlicerErrors::SliceGeneration { layer, height, .. } => {
+                (0x100B,format!("There was a issue ordering the polygon for slicing layer {} at height {:.3} mm. Try repairing your Model.",layer, height))
             }
             SlicerErrors::SettingLessThanZero { setting, value } => {
                 (0x100C,format!("The setting {} must be greater than or equal to 0. It is currently {}.",setting, value))
@@ -176,6 +276,42 @@ impl SlicerErrors {
             SlicerErrors::MovesOutsideBuildArea => {
                 (0x1014,"Slicer generated move outside build area.".to_string())
             }
+            SlicerErrors::ObjLoadError { filepath, line } => {
+                (0x1015,format!("There was a issue loading the OBJ file \"{}\" at line {}.",filepath, line))
+            }
+            SlicerErrors::AmfLoadError { filepath, element, reason } => {
+                (0x1016,format!("There was a issue loading the AMF file \"{}\" at {}: {}.",filepath, element, reason))
+            }
+            SlicerErrors::PlyLoadError { filepath, offset } => {
+                (0x1017,format!("There was a issue loading the PLY file \"{}\" at byte {}.",filepath, offset))
+            }
+            SlicerErrors::ModelsDoNotFitBuildArea { objects, required_x, required_y } => {
+                (0x1018,format!("The {} objects could not be arranged on the build plate. They need an area of at least {:.1} x {:.1} mm. Remove objects or reduce the brim and skirt.",objects, required_x, required_y))
+            }
+            SlicerErrors::LayFlatFaceNotFound { filepath, face, faces } => {
+                (0x1019,format!("Could not lay \"{}\" flat on face {}. The file only has {} faces.",filepath, face, faces))
+            }
+            SlicerErrors::ServerConnectionError { address } => {
+                (0x101A,format!("Could not listen for requests on \"{}\".",address))
+            }
+            SlicerErrors::NoModelsLoaded => {
+                (0x101B,"No models have been loaded. Load models before slicing.".to_string())
+            }
+            SlicerErrors::Cancelled => {
+                (0x101C,"The slice was cancelled.".to_string())
+            }
+            SlicerErrors::LayFlatFaceDegenerate { filepath, face } => {
+                (0x101D,format!("Could not lay \"{}\" flat on face {}. The face has no area so it has no direction.",filepath, face))
+            }
         }
     }
 }
+
+impl std::fmt::Display for SlicerErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (error_code, message) = self.get_code_and_message();
+        write!(f, "Error {:#X}: {}", error_code, message)
+    }
+}
+
+impl std::error::Error for SlicerErrors {}
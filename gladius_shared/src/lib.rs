@@ -8,6 +8,9 @@ pub mod error;
 ///Load in model files
 pub mod loader;
 
+///Write out model files
+pub mod writer;
+
 ///Settings types
 pub mod settings;
 
@@ -0,0 +1,389 @@
+use crate::loader::*;
+use serde::Deserialize;
+use std::io::Read;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "amf")]
+struct AMFFile {
+    unit: Option<String>,
+    #[serde(default)]
+    object: Vec<AMFObject>,
+    #[serde(default)]
+    constellation: Vec<AMFConstellation>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFObject {
+    id: usize,
+    mesh: AMFMesh,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFMesh {
+    vertices: AMFVertices,
+    #[serde(default)]
+    volume: Vec<AMFVolume>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFVertices {
+    #[serde(default)]
+    vertex: Vec<AMFVertex>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFVertex {
+    coordinates: AMFCoordinates,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFCoordinates {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFVolume {
+    #[serde(default)]
+    triangle: Vec<AMFTriangle>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFTriangle {
+    v1: usize,
+    v2: usize,
+    v3: usize,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFConstellation {
+    id: usize,
+    #[serde(default)]
+    instance: Vec<AMFInstance>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AMFInstance {
+    objectid: usize,
+    #[serde(default)]
+    deltax: f64,
+    #[serde(default)]
+    deltay: f64,
+    #[serde(default)]
+    deltaz: f64,
+    #[serde(default)]
+    rx: f64,
+    #[serde(default)]
+    ry: f64,
+    #[serde(default)]
+    rz: f64,
+}
+
+///Loader for AMF files, both plain and zip compressed
+pub struct AMFLoader {}
+
+impl Loader for AMFLoader {
+    fn load(
+        &self,
+        filepath: &str,
+    ) -> Result<Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>, SlicerErrors> {
+        let data = std::fs::read(filepath).map_err(|_| SlicerErrors::ObjectFileNotFound {
+            filepath: filepath.to_string(),
+        })?;
+
+        //Compressed AMF files are zip archives containing the AMF file
+        let xml = if data.starts_with(b"PK\x03\x04") {
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+                .map_err(|err| amf_error(filepath, "archive", err.to_string()))?;
+            let index = (0..archive.len())
+                .find(|index| {
+                    archive
+                        .name_for_index(*index)
+                        .map(|name| name.to_lowercase().ends_with(".amf"))
+                        .unwrap_or(false)
+                })
+                .unwrap_or(0);
+            let mut file = archive
+                .by_index(index)
+                .map_err(|err| amf_error(filepath, "archive", err.to_string()))?;
+            let part = file.name().to_string();
+            let mut xml = vec![];
+            file.read_to_end(&mut xml)
+                .map_err(|err| amf_error(filepath, part, err.to_string()))?;
+            xml
+        } else {
+            data
+        };
+
+        //Elements such as materials may be interleaved with the objects and constellations
+        let mut deserializer = serde_xml_rs::Deserializer::new_from_reader(xml.as_slice())
+            .non_contiguous_seq_elements(true);
+        let amf = AMFFile::deserialize(&mut deserializer)
+            .map_err(|err| amf_error(filepath, "amf", err.to_string()))?;
+
+        let scale = get_unit_scale(amf.unit.as_deref())
+            .ok_or_else(|| amf_error(filepath, "unit", "Unknown unit"))?;
+
+        let unit_transform = Transform::new_scale_transform(scale, scale, scale);
+
+        //Only constellations that are not instanced by another constellation are placed on the build
+        let root_constellations: Vec<&AMFConstellation> = amf
+            .constellation
+            .iter()
+            .filter(|constellation| {
+                !amf.constellation.iter().any(|other| {
+                    other
+                        .instance
+                        .iter()
+                        .any(|instance| instance.objectid == constellation.id)
+                })
+            })
+            .collect();
+
+        let mut models = vec![];
+
+        if root_constellations.is_empty() {
+            for object in &amf.object {
+                models.push(handle_object(filepath, object, &unit_transform)?);
+            }
+        } else {
+            for constellation in root_constellations {
+                handle_constellation(
+                    filepath,
+                    constellation,
+                    &amf.object,
+                    &amf.constellation,
+                    &unit_transform,
+                    0,
+                    &mut models,
+                )?;
+            }
+        }
+
+        Ok(models)
+    }
+}
+
+fn get_unit_scale(unit: Option<&str>) -> Option<f64> {
+    match unit.map(|unit| unit.to_lowercase()).as_deref() {
+        None | Some("millimeter") | Some("millimetre") | Some("mm") => Some(1.0),
+        Some("inch") | Some("in") => Some(25.4),
+        Some("feet") | Some("foot") | Some("ft") => Some(304.8),
+        Some("meter") | Some("metre") | Some("m") => Some(1000.0),
+        Some("micron") | Some("micrometer") | Some("micrometre") | Some("um") => Some(0.001),
+        Some(_) => None,
+    }
+}
+
+fn amf_error(
+    filepath: &str,
+    element: impl Into<String>,
+    reason: impl Into<String>,
+) -> SlicerErrors {
+    SlicerErrors::AmfLoadError {
+        filepath: filepath.to_string(),
+        element: element.into(),
+        reason: reason.into(),
+    }
+}
+
+fn handle_constellation(
+    filepath: &str,
+    constellation: &AMFConstellation,
+    objects: &[AMFObject],
+    constellations: &[AMFConstellation],
+    transform: &Transform,
+    depth: usize,
+    models: &mut Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>,
+) -> Result<(), SlicerErrors> {
+    //Prevent constellations that instance themselves from looping forever
+    if depth > constellations.len() {
+        return Err(amf_error(
+            filepath,
+            format!("constellation id=\"{}\"", constellation.id),
+            "Constellation instances itself",
+        ));
+    }
+
+    for instance in &constellation.instance {
+        //Deltas are in the files units so the instance is applied before scaling
+        let instance_transform = transform
+            * &(&Transform::new_translation_transform(
+                instance.deltax,
+                instance.deltay,
+                instance.deltaz,
+            ) * &Transform::new_rotation_transform(
+                instance.rx.to_radians(),
+                instance.ry.to_radians(),
+                instance.rz.to_radians(),
+            ));
+
+        if let Some(object) = objects.iter().find(|obj| obj.id == instance.objectid) {
+            models.push(handle_object(filepath, object, &instance_transform)?);
+        } else if let Some(sub_constellation) = constellations
+            .iter()
+            .find(|con| con.id == instance.objectid)
+        {
+            handle_constellation(
+                filepath,
+                sub_constellation,
+                objects,
+                constellations,
+                &instance_transform,
+                depth + 1,
+                models,
+            )?;
+        } else {
+            return Err(amf_error(
+                filepath,
+                format!("instance objectid=\"{}\"", instance.objectid),
+                "Referenced object does not exist",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_object(
+    filepath: &str,
+    object: &AMFObject,
+    transform: &Transform,
+) -> Result<(Vec<Vertex>, Vec<IndexedTriangle>), SlicerErrors> {
+    let vertices: Vec<Vertex> = object
+        .mesh
+        .vertices
+        .vertex
+        .iter()
+        .map(|vertex| {
+            transform
+                * Vertex {
+                    x: vertex.coordinates.x,
+                    y: vertex.coordinates.y,
+                    z: vertex.coordinates.z,
+                }
+        })
+        .collect();
+
+    //All volumes share the objects vertices so they are combined into a single model
+    let triangles = object
+        .mesh
+        .volume
+        .iter()
+        .flat_map(|volume| volume.triangle.iter())
+        .map(|triangle| {
+            if [triangle.v1, triangle.v2, triangle.v3]
+                .iter()
+                .any(|index| *index >= vertices.len())
+            {
+                Err(amf_error(
+                    filepath,
+                    format!("object id=\"{}\" triangle", object.id),
+                    "Triangle references a vertex that does not exist",
+                ))
+            } else {
+                Ok(lowest_vertex_first(
+                    IndexedTriangle {
+                        verts: [triangle.v1, triangle.v2, triangle.v3],
+                    },
+                    &vertices,
+                ))
+            }
+        })
+        .collect::<Result<Vec<IndexedTriangle>, SlicerErrors>>()?;
+
+    Ok((vertices, triangles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TRIANGLE_OBJECT: &str = r#"<object id="1"><mesh>
+  <vertices>
+   <vertex><coordinates><x>0</x><y>0</y><z>0</z></coordinates></vertex>
+   <vertex><coordinates><x>1</x><y>0</y><z>0</z></coordinates></vertex>
+   <vertex><coordinates><x>0</x><y>1</y><z>0</z></coordinates></vertex>
+  </vertices>
+  <volume><triangle><v1>0</v1><v2>1</v2><v3>2</v3></triangle></volume>
+ </mesh></object>"#;
+
+    fn amf(unit: &str, contents: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><amf unit="{}">{}</amf>"#,
+            unit, contents
+        )
+    }
+
+    fn load(name: &str, contents: &[u8]) -> Vec<(Vec<Vertex>, Vec<IndexedTriangle>)> {
+        AMFLoader {}
+            .load(&write_test_file(name, contents))
+            .expect("Valid AMF")
+    }
+
+    fn assert_vertex(vertex: Vertex, x: f64, y: f64, z: f64) {
+        assert!(
+            (vertex.x - x).abs() < 1e-9
+                && (vertex.y - y).abs() < 1e-9
+                && (vertex.z - z).abs() < 1e-9,
+            "{:?} != ({}, {}, {})",
+            vertex,
+            x,
+            y,
+            z
+        );
+    }
+
+    #[test]
+    fn units_are_converted_to_millimeters() {
+        let models = load("inch.amf", amf("inch", TRIANGLE_OBJECT).as_bytes());
+
+        assert_eq!(models.len(), 1);
+        assert_vertex(models[0].0[1], 25.4, 0.0, 0.0);
+        assert_eq!(models[0].1.len(), 1);
+
+        let filepath = write_test_file(
+            "unknown_unit.amf",
+            amf("furlong", TRIANGLE_OBJECT).as_bytes(),
+        );
+        assert_eq!(
+            AMFLoader {}.load(&filepath),
+            Err(amf_error(&filepath, "unit", "Unknown unit"))
+        );
+    }
+
+    #[test]
+    fn compressed_files_are_read_from_the_archive() {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        zip.start_file("model.amf", zip::write::SimpleFileOptions::default())
+            .expect("Zip is writable");
+        zip.write_all(amf("millimeter", TRIANGLE_OBJECT).as_bytes())
+            .expect("Zip is writable");
+        let data = zip.finish().expect("Zip is writable").into_inner();
+
+        let models = load("compressed.amf", &data);
+
+        assert_eq!(models.len(), 1);
+        assert_vertex(models[0].0[2], 0.0, 1.0, 0.0);
+    }
+
+    #[test]
+    fn constellations_place_instances() {
+        //Deltas are in the file units and rotations in degrees
+        let contents = format!(
+            r#"{}<constellation id="2">
+  <instance objectid="1"><deltax>10</deltax></instance>
+  <instance objectid="1"><deltax>1</deltax><deltay>2</deltay><rz>90</rz></instance>
+ </constellation>"#,
+            TRIANGLE_OBJECT
+        );
+        let models = load("constellation.amf", amf("meter", &contents).as_bytes());
+
+        assert_eq!(models.len(), 2);
+        assert_vertex(models[0].0[1], 11000.0, 0.0, 0.0);
+        assert_vertex(models[1].0[0], 1000.0, 2000.0, 0.0);
+        assert_vertex(models[1].0[1], 1000.0, 3000.0, 0.0);
+    }
+}
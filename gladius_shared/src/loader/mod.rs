@@ -3,11 +3,23 @@
 use crate::error::*;
 use crate::types::*;
 
+mod amf;
+mod obj;
+mod ply;
 mod stl;
 mod threemf;
+mod threemf_config;
 
+pub use amf::AMFLoader;
+pub use obj::OBJLoader;
+pub use ply::PLYLoader;
 pub use stl::STLLoader;
 pub use threemf::ThreeMFLoader;
+pub use threemf_config::{ProjectSettings, ProjectTemperatures};
+pub(crate) use threemf_config::{OBJECT_SETTINGS_PART, SETTINGS_PART};
+
+///A loaded model along with its identity
+type ModelWithInfo = (ObjectInfo, Vec<Vertex>, Vec<IndexedTriangle>);
 
 ///Loader trait to define loading in a file type of a model into a triangles and vertices
 pub trait Loader {
@@ -16,4 +28,38 @@ pub trait Loader {
         &self,
         filepath: &str,
     ) -> Result<Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>, SlicerErrors>;
+
+    ///Load a specifc file along with the identity of each model in it
+    fn load_with_info(&self, filepath: &str) -> Result<Vec<ModelWithInfo>, SlicerErrors> {
+        Ok(self
+            .load(filepath)?
+            .into_iter()
+            .map(|(v, t)| (ObjectInfo::default(), v, t))
+            .collect())
+    }
+}
+
+///Rotate the triangles vertices so the lowest vertex is first while maintaining the winding order
+pub fn lowest_vertex_first(mut triangle: IndexedTriangle, vertices: &[Vertex]) -> IndexedTriangle {
+    let v0 = vertices[triangle.verts[0]];
+    let v1 = vertices[triangle.verts[1]];
+    let v2 = vertices[triangle.verts[2]];
+
+    if v0 < v1 && v0 < v2 {
+        triangle
+    } else if v1 < v2 && v1 < v0 {
+        triangle.verts.rotate_left(1);
+        triangle
+    } else {
+        triangle.verts.rotate_right(1);
+        triangle
+    }
+}
+
+///Write the contents to a file in the temp directory that is unique to the test
+#[cfg(test)]
+pub(crate) fn write_test_file(name: &str, contents: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("gladius_{}_{}", std::process::id(), name));
+    std::fs::write(&path, contents).expect("Temp directory should be writable");
+    path.to_string_lossy().to_string()
 }
@@ -0,0 +1,234 @@
+use crate::loader::*;
+use std::collections::HashMap;
+
+///Loader for Wavefront OBJ files
+pub struct OBJLoader {}
+
+impl Loader for OBJLoader {
+    fn load(
+        &self,
+        filepath: &str,
+    ) -> Result<Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>, SlicerErrors> {
+        let contents =
+            std::fs::read_to_string(filepath).map_err(|_| SlicerErrors::ObjectFileNotFound {
+                filepath: filepath.to_string(),
+            })?;
+
+        let mut vertices: Vec<Vertex> = vec![];
+        let mut groups: Vec<ObjGroup> = vec![ObjGroup::default()];
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let line_error = || SlicerErrors::ObjLoadError {
+                filepath: filepath.to_string(),
+                line: line_index + 1,
+            };
+
+            //Remove comments and surrounding whitespace
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("v") => {
+                    let coords: Vec<f64> = tokens
+                        .take(3)
+                        .map(|token| token.parse::<f64>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| line_error())?;
+
+                    if coords.len() != 3 {
+                        return Err(line_error());
+                    }
+
+                    vertices.push(Vertex {
+                        x: coords[0],
+                        y: coords[1],
+                        z: coords[2],
+                    });
+                }
+                Some("f") => {
+                    let face: Vec<usize> = tokens
+                        .map(|token| parse_face_index(token, vertices.len()))
+                        .collect::<Option<_>>()
+                        .ok_or_else(line_error)?;
+
+                    if face.len() < 3 {
+                        return Err(line_error());
+                    }
+
+                    groups
+                        .last_mut()
+                        .expect("Groups always contains the default group")
+                        .faces
+                        .push(face);
+                }
+                Some("o") | Some("g") => {
+                    //Start a new model, reuse the current one if nothing was added to it
+                    if groups
+                        .last()
+                        .expect("Groups always contains the default group")
+                        .faces
+                        .is_empty()
+                    {
+                        groups.pop();
+                    }
+                    groups.push(ObjGroup::default());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(groups
+            .into_iter()
+            .filter(|group| !group.faces.is_empty())
+            .map(|group| group.into_model(&vertices))
+            .collect())
+    }
+}
+
+#[derive(Default)]
+struct ObjGroup {
+    faces: Vec<Vec<usize>>,
+}
+
+impl ObjGroup {
+    fn into_model(self, vertices: &[Vertex]) -> (Vec<Vertex>, Vec<IndexedTriangle>) {
+        //Only keep the vertices used by this group and re-index them
+        let mut index_map: HashMap<usize, usize> = HashMap::new();
+        let mut group_vertices = vec![];
+
+        let mut local_index = |global_index: usize| {
+            *index_map.entry(global_index).or_insert_with(|| {
+                group_vertices.push(vertices[global_index]);
+                group_vertices.len() - 1
+            })
+        };
+
+        let triangles: Vec<[usize; 3]> = self
+            .faces
+            .iter()
+            .flat_map(|face| {
+                //Fan triangulation of polygonal faces
+                (1..face.len() - 1)
+                    .map(|i| {
+                        [
+                            local_index(face[0]),
+                            local_index(face[i]),
+                            local_index(face[i + 1]),
+                        ]
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let triangles = triangles
+            .into_iter()
+            .map(|verts| lowest_vertex_first(IndexedTriangle { verts }, &group_vertices))
+            .collect();
+
+        (group_vertices, triangles)
+    }
+}
+
+///Parse a single face element ( v, v/vt, v//vn or v/vt/vn ) into a zero based vertex index
+fn parse_face_index(token: &str, vertex_count: usize) -> Option<usize> {
+    let index: isize = token
+        .split('/')
+        .next()
+        .and_then(|index| index.parse().ok())?;
+
+    //Positive indices are 1 based, negative indices are relative to the end of the vertex list
+    let index = if index > 0 {
+        index - 1
+    } else {
+        vertex_count as isize + index
+    };
+
+    if index < 0 || index as usize >= vertex_count {
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, contents: &str) -> Vec<(Vec<Vertex>, Vec<IndexedTriangle>)> {
+        OBJLoader {}
+            .load(&write_test_file(name, contents.as_bytes()))
+            .expect("Valid OBJ")
+    }
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let models = load(
+            "ngon.obj",
+            "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1/1 2/2/2 3//3 4 5\n",
+        );
+
+        assert_eq!(models.len(), 1);
+        let (vertices, triangles) = &models[0];
+        assert_eq!(vertices.len(), 5);
+        assert_eq!(triangles.len(), 3);
+
+        //Every triangle shares the first vertex of the face
+        for triangle in triangles {
+            assert!(triangle.verts.contains(&0));
+        }
+    }
+
+    #[test]
+    fn negative_indices_are_relative_to_the_last_vertex() {
+        let models = load(
+            "negative.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 0 0 1\nf 1 -2 -1\n",
+        );
+
+        let (vertices, triangles) = &models[0];
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(triangles[0].verts, [0, 1, 2]);
+        assert_eq!(
+            vertices[triangles[1].verts[2]],
+            Vertex {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0
+            }
+        );
+    }
+
+    #[test]
+    fn objects_and_groups_are_separate_models() {
+        let models = load(
+            "groups.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 5 5 5\nv 6 5 5\nv 5 6 5\no first\ng first_group\nf 1 2 3\ng second\nf 4 5 6\n",
+        );
+
+        //The empty object is merged into the group that follows it
+        assert_eq!(models.len(), 2);
+
+        //Each model only keeps its own vertices
+        let (vertices, triangles) = &models[1];
+        assert_eq!(vertices.len(), 3);
+        assert_eq!(triangles[0].verts, [0, 1, 2]);
+        assert_eq!(
+            vertices[0],
+            Vertex {
+                x: 5.0,
+                y: 5.0,
+                z: 5.0
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_faces_report_the_line() {
+        let filepath = write_test_file("invalid.obj", b"v 0 0 0\nv 1 0 0\n# comment\nf 1 2 3\n");
+
+        assert_eq!(
+            OBJLoader {}.load(&filepath),
+            Err(SlicerErrors::ObjLoadError { filepath, line: 4 })
+        );
+    }
+}
@@ -0,0 +1,495 @@
+use crate::loader::*;
+
+///Loader for PLY files in ascii or binary format
+pub struct PLYLoader {}
+
+impl Loader for PLYLoader {
+    fn load(
+        &self,
+        filepath: &str,
+    ) -> Result<Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>, SlicerErrors> {
+        let data = std::fs::read(filepath).map_err(|_| SlicerErrors::ObjectFileNotFound {
+            filepath: filepath.to_string(),
+        })?;
+
+        let model = parse_ply(&data).map_err(|offset| SlicerErrors::PlyLoadError {
+            filepath: filepath.to_string(),
+            offset,
+        })?;
+
+        Ok(vec![model])
+    }
+}
+
+///Parse the PLY data into a model. Errors are the byte offset of the invalid data
+fn parse_ply(data: &[u8]) -> Result<(Vec<Vertex>, Vec<IndexedTriangle>), usize> {
+    let (header, body_start) = parse_header(data)?;
+    let body = &data[body_start..];
+
+    let mut reader: Box<dyn PlyReader> = match header.format {
+        PlyFormat::Ascii => Box::new(AsciiReader {
+            text: std::str::from_utf8(body).map_err(|err| body_start + err.valid_up_to())?,
+            position: 0,
+        }),
+        PlyFormat::BinaryLittleEndian => Box::new(BinaryReader {
+            data: body,
+            position: 0,
+            little_endian: true,
+        }),
+        PlyFormat::BinaryBigEndian => Box::new(BinaryReader {
+            data: body,
+            position: 0,
+            little_endian: false,
+        }),
+    };
+
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut faces: Vec<(usize, Vec<usize>)> = vec![];
+
+    //Every element must be read in order even if unused as the file has no offsets
+    for element in &header.elements {
+        for _ in 0..element.count {
+            let row_offset = body_start + reader.position();
+            let mut position = [None; 3];
+            let mut face = None;
+
+            for property in &element.properties {
+                match property {
+                    PlyProperty::Scalar { name, data_type } => {
+                        let value = reader
+                            .read_value(*data_type)
+                            .ok_or_else(|| body_start + reader.position())?;
+                        match name.as_str() {
+                            "x" => position[0] = Some(value),
+                            "y" => position[1] = Some(value),
+                            "z" => position[2] = Some(value),
+                            _ => {}
+                        }
+                    }
+                    PlyProperty::List {
+                        name,
+                        count_type,
+                        item_type,
+                    } => {
+                        let count_offset = body_start + reader.position();
+                        let count = reader
+                            .read_value(*count_type)
+                            .and_then(to_index)
+                            .ok_or(count_offset)?;
+
+                        //Not preallocated as the count may be far larger than the data
+                        let mut items = vec![];
+                        for _ in 0..count {
+                            items.push(
+                                reader
+                                    .read_value(*item_type)
+                                    .ok_or_else(|| body_start + reader.position())?,
+                            );
+                        }
+
+                        if name == "vertex_indices" || name == "vertex_index" {
+                            face = Some(items);
+                        }
+                    }
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    if let [Some(x), Some(y), Some(z)] = position {
+                        vertices.push(Vertex { x, y, z });
+                    } else {
+                        return Err(row_offset);
+                    }
+                }
+                "face" => {
+                    let face = face
+                        .ok_or(row_offset)?
+                        .into_iter()
+                        .map(to_index)
+                        .collect::<Option<Vec<usize>>>()
+                        .ok_or(row_offset)?;
+                    faces.push((row_offset, face));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut triangles = vec![];
+    for (offset, face) in faces {
+        if face.len() < 3 || face.iter().any(|index| *index >= vertices.len()) {
+            return Err(offset);
+        }
+
+        //Polygons are triangulated as a fan around the first vertex
+        for window in 1..face.len() - 1 {
+            triangles.push(lowest_vertex_first(
+                IndexedTriangle {
+                    verts: [face[0], face[window], face[window + 1]],
+                },
+                &vertices,
+            ));
+        }
+    }
+
+    Ok((vertices, triangles))
+}
+
+///Convert a value read from the file into an index, rejecting values that are not whole numbers
+fn to_index(value: f64) -> Option<usize> {
+    if value.is_finite() && value >= 0.0 && value.fract() == 0.0 {
+        Some(value as usize)
+    } else {
+        None
+    }
+}
+
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum PlyDataType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyDataType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(PlyDataType::Int8),
+            "uchar" | "uint8" => Some(PlyDataType::UInt8),
+            "short" | "int16" => Some(PlyDataType::Int16),
+            "ushort" | "uint16" => Some(PlyDataType::UInt16),
+            "int" | "int32" => Some(PlyDataType::Int32),
+            "uint" | "uint32" => Some(PlyDataType::UInt32),
+            "float" | "float32" => Some(PlyDataType::Float32),
+            "double" | "float64" => Some(PlyDataType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PlyDataType::Int8 | PlyDataType::UInt8 => 1,
+            PlyDataType::Int16 | PlyDataType::UInt16 => 2,
+            PlyDataType::Int32 | PlyDataType::UInt32 | PlyDataType::Float32 => 4,
+            PlyDataType::Float64 => 8,
+        }
+    }
+}
+
+enum PlyProperty {
+    Scalar {
+        name: String,
+        data_type: PlyDataType,
+    },
+    List {
+        name: String,
+        count_type: PlyDataType,
+        item_type: PlyDataType,
+    },
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+struct PlyHeader {
+    format: PlyFormat,
+    elements: Vec<PlyElement>,
+}
+
+///Parse the header returning it and the offset of the body. Errors are the byte offset of the invalid line
+fn parse_header(data: &[u8]) -> Result<(PlyHeader, usize), usize> {
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    let mut line_offset = 0;
+    let mut body_start = None;
+
+    for (index, line) in data.split(|byte| *byte == b'\n').enumerate() {
+        let offset = line_offset;
+        line_offset += line.len() + 1;
+
+        let line = std::str::from_utf8(line).map_err(|err| offset + err.valid_up_to())?;
+
+        if index > 0 && line.trim() == "end_header" {
+            //The body starts after the line ending following end_header
+            if line_offset > data.len() {
+                return Err(offset);
+            }
+            body_start = Some(line_offset);
+            break;
+        }
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if index == 0 {
+            if tokens.as_slice() != ["ply"] {
+                return Err(offset);
+            }
+            continue;
+        }
+
+        match tokens.as_slice() {
+            ["format", format_name, _version] => {
+                format = Some(match *format_name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(offset),
+                });
+            }
+            ["element", name, count] => {
+                elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| offset)?,
+                    properties: vec![],
+                });
+            }
+            ["property", "list", count_type, item_type, name] => {
+                elements
+                    .last_mut()
+                    .ok_or(offset)?
+                    .properties
+                    .push(PlyProperty::List {
+                        name: name.to_string(),
+                        count_type: PlyDataType::from_name(count_type).ok_or(offset)?,
+                        item_type: PlyDataType::from_name(item_type).ok_or(offset)?,
+                    });
+            }
+            ["property", data_type, name] => {
+                elements
+                    .last_mut()
+                    .ok_or(offset)?
+                    .properties
+                    .push(PlyProperty::Scalar {
+                        name: name.to_string(),
+                        data_type: PlyDataType::from_name(data_type).ok_or(offset)?,
+                    });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(offset),
+        }
+    }
+
+    Ok((
+        PlyHeader {
+            format: format.ok_or(0usize)?,
+            elements,
+        },
+        body_start.ok_or(0usize)?,
+    ))
+}
+
+trait PlyReader {
+    ///Read the next value, returns None if the data is missing or invalid
+    fn read_value(&mut self, data_type: PlyDataType) -> Option<f64>;
+
+    ///The current offset into the body
+    fn position(&self) -> usize;
+}
+
+struct AsciiReader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl PlyReader for AsciiReader<'_> {
+    fn read_value(&mut self, _data_type: PlyDataType) -> Option<f64> {
+        let remaining = &self.text[self.position..];
+        let token_start = remaining.len() - remaining.trim_start().len();
+        let token = remaining[token_start..].split_ascii_whitespace().next()?;
+
+        //Leave the position at the start of an invalid token
+        self.position += token_start;
+        let value = token.parse::<f64>().ok()?;
+        self.position += token.len();
+
+        Some(value)
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    little_endian: bool,
+}
+
+impl PlyReader for BinaryReader<'_> {
+    fn read_value(&mut self, data_type: PlyDataType) -> Option<f64> {
+        let size = data_type.size();
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(self.data.get(self.position..self.position + size)?);
+        self.position += size;
+
+        //Convert to little endian so a single decode is needed
+        if !self.little_endian {
+            bytes[..size].reverse();
+        }
+
+        Some(match data_type {
+            PlyDataType::Int8 => i8::from_le_bytes([bytes[0]]) as f64,
+            PlyDataType::UInt8 => bytes[0] as f64,
+            PlyDataType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyDataType::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyDataType::Int32 => {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            PlyDataType::UInt32 => {
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            PlyDataType::Float32 => {
+                f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            PlyDataType::Float64 => f64::from_le_bytes(bytes),
+        })
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    #[test]
+    fn invalid_indices_are_rejected() {
+        for index in ["-1", "1.5", "nan", "inf"] {
+            let data = format!("{}0 0 0\n1 0 0\n0 1 0\n3 0 {} 2\n", HEADER, index);
+
+            //The row offset is at the end of the previous row
+            assert_eq!(
+                parse_ply(data.as_bytes()).map(|_| ()),
+                Err(HEADER.len() + 17),
+                "index {}",
+                index
+            );
+        }
+    }
+
+    ///A square with the properties in an unusual order and extra properties to skip
+    fn binary_square(little_endian: bool) -> Vec<u8> {
+        let format = if little_endian {
+            "binary_little_endian"
+        } else {
+            "binary_big_endian"
+        };
+        let mut data = format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty double z\nproperty uchar red\nproperty float x\nproperty float y\nelement face 1\nproperty list uchar short other\nproperty list uchar uint vertex_indices\nend_header\n",
+            format
+        )
+        .into_bytes();
+
+        for (x, y) in [(0.0f32, 0.0f32), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)] {
+            let z = 1.0f64;
+            if little_endian {
+                data.extend(z.to_le_bytes());
+                data.push(255);
+                data.extend(x.to_le_bytes());
+                data.extend(y.to_le_bytes());
+            } else {
+                data.extend(z.to_be_bytes());
+                data.push(255);
+                data.extend(x.to_be_bytes());
+                data.extend(y.to_be_bytes());
+            }
+        }
+
+        data.extend([1, 0, 7]);
+        data.push(4);
+        for index in 0u32..4 {
+            if little_endian {
+                data.extend(index.to_le_bytes());
+            } else {
+                data.extend(index.to_be_bytes());
+            }
+        }
+
+        data
+    }
+
+    #[test]
+    fn binary_and_ascii_files_match() {
+        let ascii = "ply\nformat ascii 1.0\ncomment made by hand\nelement vertex 4\nproperty double z\nproperty uchar red\nproperty float x\nproperty float y\nelement face 1\nproperty list uchar short other\nproperty list uchar uint vertex_indices\nend_header\n1 255 0 0\n1 255 2 0\n1 255 2 2\n1 255 0 2\n1 7 4 0 1 2 3\n";
+
+        let expected = parse_ply(ascii.as_bytes()).expect("Valid ascii PLY");
+
+        assert_eq!(
+            expected.0[2],
+            Vertex {
+                x: 2.0,
+                y: 2.0,
+                z: 1.0
+            }
+        );
+
+        //The square is split into 2 triangles
+        assert_eq!(expected.1.len(), 2);
+
+        for little_endian in [true, false] {
+            assert_eq!(
+                parse_ply(&binary_square(little_endian)),
+                Ok(expected.clone()),
+                "little endian {}",
+                little_endian
+            );
+        }
+    }
+
+    #[test]
+    fn end_header_in_comments_is_not_the_end() {
+        let data = binary_square(true);
+        let mut commented =
+            b"ply\ncomment the header ends at end_header\nobj_info end_header\n".to_vec();
+        commented.extend(&data[b"ply\n".len()..]);
+
+        assert_eq!(parse_ply(&commented), parse_ply(&data));
+        assert!(parse_ply(&commented).is_ok());
+    }
+
+    #[test]
+    fn truncated_binary_files_report_the_offset() {
+        let data = binary_square(true);
+        let truncated = &data[..data.len() - 2];
+
+        //The last index is missing
+        assert_eq!(parse_ply(truncated).map(|_| ()), Err(data.len() - 4));
+    }
+
+    #[test]
+    fn huge_list_counts_are_errors() {
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement face 1\nproperty list uint int vertex_indices\nend_header\n".to_vec();
+        let body_start = data.len();
+        data.extend(u32::MAX.to_le_bytes());
+        data.extend(0i32.to_le_bytes());
+
+        //The second item is missing
+        assert_eq!(parse_ply(&data).map(|_| ()), Err(body_start + 8));
+
+        let ascii = format!(
+            "{}1e300 0 1 2\n",
+            HEADER.replace("element vertex 3", "element vertex 0")
+        );
+        assert!(parse_ply(ascii.as_bytes()).is_err());
+    }
+}
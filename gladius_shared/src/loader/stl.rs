@@ -1,5 +1,5 @@
 use crate::loader::*;
-use std::io::BufReader;
+use std::io::Cursor;
 
 ///Loader for STL files
 pub struct STLLoader {}
@@ -9,16 +9,19 @@ impl Loader for STLLoader {
         &self,
         filepath: &str,
     ) -> Result<Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>, SlicerErrors> {
-        let file = std::fs::OpenOptions::new()
-            .read(true)
-            .open(filepath)
-            .map_err(|_| SlicerErrors::ObjectFileNotFound {
-                filepath: filepath.to_string(),
-            })?;
+        let data = std::fs::read(filepath).map_err(|_| SlicerErrors::ObjectFileNotFound {
+            filepath: filepath.to_string(),
+        })?;
 
-        let mut root_vase = BufReader::new(&file);
-        let mesh: nom_stl::IndexMesh = nom_stl::parse_stl(&mut root_vase)
-            .map_err(|_| SlicerErrors::StlLoadError)?
+        let mesh: nom_stl::IndexMesh = nom_stl::parse_stl(&mut Cursor::new(&data))
+            .map_err(|_| {
+                let (offset, line) = find_stl_error(&data);
+                SlicerErrors::StlLoadError {
+                    filepath: filepath.to_string(),
+                    offset,
+                    line,
+                }
+            })?
             .into();
 
         let mut triangles = vec![];
@@ -33,46 +36,58 @@ impl Loader for STLLoader {
             .collect::<Vec<Vertex>>();
 
         for triangle in mesh.triangles() {
-            let mut converted_tri = IndexedTriangle {
+            let converted_tri = IndexedTriangle {
                 verts: [
                     triangle.vertices_indices()[0],
                     triangle.vertices_indices()[1],
                     triangle.vertices_indices()[2],
                 ],
             };
-            /*
-                        let A = v1.x * v0.y + v2.x * v1.y + v0.x * v2.y;
-                        let B = v0.x * v1.y + v1.x * v2.y + v2.x * v0.y;
 
-                        if  A < B
-                        {
-                            let temp = converted_tri.verts[0];
-                            converted_tri.verts[0] = converted_tri.verts[1];
-                            converted_tri.verts[1] = temp;
-                            std::mem::swap(&mut v0, &mut v1);
-                        }
-            */
-            let v0 = vertices[converted_tri.verts[0]];
-            let v1 = vertices[converted_tri.verts[1]];
-            let v2 = vertices[converted_tri.verts[2]];
-
-            if v0 < v1 && v0 < v2 {
-                triangles.push(converted_tri);
-            } else if v1 < v2 && v1 < v0 {
-                let temp = converted_tri.verts[0];
-                converted_tri.verts[0] = converted_tri.verts[1];
-                converted_tri.verts[1] = converted_tri.verts[2];
-                converted_tri.verts[2] = temp;
-                triangles.push(converted_tri);
-            } else {
-                let temp = converted_tri.verts[0];
-                converted_tri.verts[0] = converted_tri.verts[2];
-                converted_tri.verts[2] = converted_tri.verts[1];
-                converted_tri.verts[1] = temp;
-                triangles.push(converted_tri);
-            }
+            triangles.push(lowest_vertex_first(converted_tri, &vertices));
         }
 
         Ok(vec![(vertices, triangles)])
     }
 }
+
+///Find the location of the invalid data in a STL file that failed to parse.
+///Returns the byte offset for binary files and the line for ascii files
+fn find_stl_error(data: &[u8]) -> (Option<usize>, Option<usize>) {
+    let is_ascii = data.starts_with(b"solid")
+        && data
+            .windows(b"facet".len())
+            .any(|window| window == b"facet");
+
+    if is_ascii {
+        let text = String::from_utf8_lossy(data);
+        let line = text
+            .lines()
+            .position(|line| {
+                let mut tokens = line.split_whitespace();
+                match tokens.next() {
+                    Some("vertex") => {
+                        tokens.filter(|token| token.parse::<f32>().is_ok()).count() != 3
+                    }
+                    Some("solid") | Some("facet") | Some("outer") | Some("endloop")
+                    | Some("endfacet") | Some("endsolid") | None => false,
+                    Some(_) => true,
+                }
+            })
+            .map(|index| index + 1);
+
+        (None, line)
+    } else if data.len() < 84 {
+        (Some(data.len()), None)
+    } else {
+        //Binary files are an 80 byte header, triangle count and 50 bytes per triangle
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        let complete_triangles = (data.len() - 84) / 50;
+
+        if complete_triangles < count {
+            (Some(84 + complete_triangles * 50), None)
+        } else {
+            (None, None)
+        }
+    }
+}
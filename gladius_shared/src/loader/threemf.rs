@@ -1,6 +1,7 @@
 use crate::error::SlicerErrors;
 use crate::loader::*;
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Deserialize, Debug)]
 struct Relationships {
@@ -21,6 +22,8 @@ struct Relationship {
 #[derive(Deserialize, Debug)]
 #[serde(rename = "model")]
 struct ThreeMFModel {
+    #[serde(default)]
+    metadata: Vec<ThreeMFMetadata>,
     resources: ThreeMFResource,
     build: ThreeMFBuild,
 }
@@ -39,12 +42,30 @@ struct ThreeMFObject {
     mesh: Option<ThreeMFMesh>,
     components: Option<ThreeMFComponents>,
     id: usize,
+    name: Option<String>,
+    partnumber: Option<String>,
+    metadatagroup: Option<ThreeMFMetadataGroup>,
 }
 
 #[derive(Deserialize, Debug)]
 struct ThreeMFItem {
     objectid: usize,
     transform: Option<String>,
+    partnumber: Option<String>,
+    metadatagroup: Option<ThreeMFMetadataGroup>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ThreeMFMetadataGroup {
+    #[serde(default)]
+    metadata: Vec<ThreeMFMetadata>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ThreeMFMetadata {
+    name: String,
+    #[serde(rename = "$value", default)]
+    value: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -92,6 +113,14 @@ impl Loader for ThreeMFLoader {
         &self,
         filepath: &str,
     ) -> Result<Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>, SlicerErrors> {
+        Ok(self
+            .load_with_info(filepath)?
+            .into_iter()
+            .map(|(_info, v, t)| (v, t))
+            .collect())
+    }
+
+    fn load_with_info(&self, filepath: &str) -> Result<Vec<ModelWithInfo>, SlicerErrors> {
         let zipfile =
             std::fs::File::open(filepath).map_err(|_| SlicerErrors::ObjectFileNotFound {
                 filepath: filepath.to_string(),
@@ -103,46 +132,125 @@ impl Loader for ThreeMFLoader {
         let rel_file = match archive.by_name("_rels/.rels") {
             Ok(file) => file,
             Err(..) => {
-                return Err(SlicerErrors::ThreemfLoadError);
+                return Err(threemf_error(
+                    filepath,
+                    "_rels/.rels",
+                    "The relationships part is missing",
+                ));
             }
         };
 
-        let rel: Relationships =
-            serde_xml_rs::de::from_reader(rel_file).map_err(|_| SlicerErrors::ThreemfLoadError)?;
+        let rel: Relationships = serde_xml_rs::de::from_reader(rel_file)
+            .map_err(|err| threemf_error(filepath, "_rels/.rels", err.to_string()))?;
 
-        let model_path = rel.relationship[0].target.clone();
+        let model_path = rel
+            .relationship
+            .first()
+            .ok_or_else(|| threemf_error(filepath, "_rels/.rels", "No relationships found"))?
+            .target
+            .clone();
 
         let model_file = match archive.by_name(&model_path[1..]) {
             Ok(file) => file,
             Err(..) => {
-                return Err(SlicerErrors::ThreemfLoadError);
+                return Err(threemf_error(
+                    filepath,
+                    &model_path,
+                    "The model part is missing",
+                ));
             }
         };
 
         let model: ThreeMFModel = serde_xml_rs::de::from_reader(model_file)
-            .map_err(|_| SlicerErrors::ThreemfLoadError)?;
+            .map_err(|err| threemf_error(filepath, &model_path, err.to_string()))?;
+
+        //Count how many times each object has been placed so far
+        let mut instances: HashMap<usize, usize> = HashMap::new();
 
         model
             .build
             .item
             .iter()
             .map(|item| {
-                let (mut v, t) = handle_object(item.objectid, &model.resources)?;
+                let (mut v, t) = handle_object(filepath, item.objectid, &model.resources)?;
 
                 if let Some(t_str) = &item.transform {
-                    let transform = get_transform_from_string(t_str)?;
+                    let transform = get_transform_from_string(t_str).ok_or_else(|| {
+                        threemf_error(
+                            filepath,
+                            format!("item objectid=\"{}\"", item.objectid),
+                            "The transform must be 12 numbers",
+                        )
+                    })?;
 
                     for vert in v.iter_mut() {
                         *vert = &transform * *vert;
                     }
                 }
-                Ok((v, t))
+
+                let instance = instances.entry(item.objectid).or_insert(0);
+                let info = get_object_info(item, *instance, &model);
+                *instance += 1;
+
+                Ok((info, v, t))
             })
             .collect()
     }
 }
 
+///Build the identity of a build item from the item, its object and the model metadata.
+///Item values take precedence over object values which take precedence over model values.
+fn get_object_info(item: &ThreeMFItem, instance: usize, model: &ThreeMFModel) -> ObjectInfo {
+    let object = model
+        .resources
+        .object
+        .iter()
+        .find(|obj| obj.id == item.objectid);
+
+    let metadata = model
+        .metadata
+        .iter()
+        .chain(
+            object
+                .and_then(|obj| obj.metadatagroup.as_ref())
+                .into_iter()
+                .flat_map(|group| group.metadata.iter()),
+        )
+        .chain(
+            item.metadatagroup
+                .iter()
+                .flat_map(|group| group.metadata.iter()),
+        )
+        .map(|meta| (meta.name.clone(), meta.value.clone()))
+        .collect();
+
+    ObjectInfo {
+        name: object.and_then(|obj| obj.name.clone()),
+        part_number: item
+            .partnumber
+            .clone()
+            .or_else(|| object.and_then(|obj| obj.partnumber.clone())),
+        source_id: Some(item.objectid),
+        instance,
+        metadata,
+        settings: None,
+    }
+}
+
+fn threemf_error(
+    filepath: &str,
+    element: impl Into<String>,
+    reason: impl Into<String>,
+) -> SlicerErrors {
+    SlicerErrors::ThreemfLoadError {
+        filepath: filepath.to_string(),
+        element: element.into(),
+        reason: reason.into(),
+    }
+}
+
 fn handle_object(
+    filepath: &str,
     obj_index: usize,
     comps: &ThreeMFResource,
 ) -> Result<(Vec<Vertex>, Vec<IndexedTriangle>), SlicerErrors> {
@@ -150,19 +258,37 @@ fn handle_object(
         .object
         .iter()
         .find(|obj| obj.id == obj_index)
-        .ok_or(SlicerErrors::ThreemfLoadError)?;
+        .ok_or_else(|| {
+            threemf_error(
+                filepath,
+                format!("object id=\"{}\"", obj_index),
+                "Referenced object does not exist",
+            )
+        })?;
 
     if let Some(mesh) = &object.mesh {
-        Ok(handle_mesh(mesh))
+        handle_mesh(mesh).ok_or_else(|| {
+            threemf_error(
+                filepath,
+                format!("object id=\"{}\" triangle", obj_index),
+                "Triangle references a vertex that does not exist",
+            )
+        })
     } else if let Some(components) = &object.components {
         let mut v = vec![];
         let mut t = vec![];
         let mut start = 0;
         for component in &components.component {
-            let (mut verts, mut triangles) = handle_object(component.objectid, comps)?;
+            let (mut verts, mut triangles) = handle_object(filepath, component.objectid, comps)?;
 
             if let Some(t_str) = &component.transform {
-                let transform = get_transform_from_string(t_str)?;
+                let transform = get_transform_from_string(t_str).ok_or_else(|| {
+                    threemf_error(
+                        filepath,
+                        format!("component objectid=\"{}\"", component.objectid),
+                        "The transform must be 12 numbers",
+                    )
+                })?;
 
                 for vert in verts.iter_mut() {
                     *vert = &transform * *vert;
@@ -185,49 +311,44 @@ fn handle_object(
 
         Ok((v, t))
     } else {
-        Err(SlicerErrors::ThreemfLoadError)
+        Err(threemf_error(
+            filepath,
+            format!("object id=\"{}\"", obj_index),
+            "Object has no mesh or components",
+        ))
     }
 }
 
-fn handle_mesh(mesh: &ThreeMFMesh) -> (Vec<Vertex>, Vec<IndexedTriangle>) {
+fn handle_mesh(mesh: &ThreeMFMesh) -> Option<(Vec<Vertex>, Vec<IndexedTriangle>)> {
     let mut triangles = vec![];
     let vertices = mesh.vertices.list.clone();
 
     for triangle in &mesh.triangles.list {
-        let mut converted_tri = IndexedTriangle {
+        let converted_tri = IndexedTriangle {
             verts: [triangle.v1, triangle.v2, triangle.v3],
         };
-        let v0 = vertices[converted_tri.verts[0]];
-        let v1 = vertices[converted_tri.verts[1]];
-        let v2 = vertices[converted_tri.verts[2]];
-
-        if v0 < v1 && v0 < v2 {
-            triangles.push(converted_tri);
-        } else if v1 < v2 && v1 < v0 {
-            let temp = converted_tri.verts[0];
-            converted_tri.verts[0] = converted_tri.verts[1];
-            converted_tri.verts[1] = converted_tri.verts[2];
-            converted_tri.verts[2] = temp;
-            triangles.push(converted_tri);
-        } else {
-            let temp = converted_tri.verts[0];
-            converted_tri.verts[0] = converted_tri.verts[2];
-            converted_tri.verts[2] = converted_tri.verts[1];
-            converted_tri.verts[1] = temp;
-            triangles.push(converted_tri);
+
+        if converted_tri
+            .verts
+            .iter()
+            .any(|index| *index >= vertices.len())
+        {
+            return None;
         }
+
+        triangles.push(lowest_vertex_first(converted_tri, &vertices));
     }
 
-    (vertices, triangles)
+    Some((vertices, triangles))
 }
 
-fn get_transform_from_string(transform_string: &str) -> Result<Transform, SlicerErrors> {
+fn get_transform_from_string(transform_string: &str) -> Option<Transform> {
     let res_values: Result<Vec<f64>, _> =
         transform_string.split(' ').map(|str| str.parse()).collect();
 
-    let values = res_values.map_err(|_| SlicerErrors::ThreemfLoadError)?;
+    let values = res_values.ok()?;
     if values.len() != 12 {
-        Err(SlicerErrors::ThreemfLoadError)
+        None
     } else {
         let t = [
             [values[0], values[3], values[6], values[9]],
@@ -235,6 +356,6 @@ fn get_transform_from_string(transform_string: &str) -> Result<Transform, Slicer
             [values[2], values[5], values[8], values[11]],
             [0.0, 0.0, 0.0, 1.0],
         ];
-        Ok(Transform(t))
+        Some(Transform(t))
     }
 }
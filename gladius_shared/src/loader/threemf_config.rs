@@ -0,0 +1,518 @@
+use crate::loader::*;
+use crate::settings::{
+    FilamentSettings, LayerRange, PartialLayerSettings, PartialSettings, Settings,
+};
+use crate::warning::SlicerWarnings;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+
+///The part containing the complete settings written by this slicer
+pub(crate) const SETTINGS_PART: &str = "Metadata/gladius_settings.json";
+
+///The part containing the per object settings written by this slicer
+pub(crate) const OBJECT_SETTINGS_PART: &str = "Metadata/gladius_object_settings.json";
+
+///The slicer settings embedded in a 3MF project file
+#[derive(Debug, Default)]
+pub struct ProjectSettings {
+    ///Settings that apply to every object in the file
+    pub settings: PartialSettings,
+
+    ///Temperatures that apply to every layer of every object in the file
+    pub temperatures: ProjectTemperatures,
+
+    ///Settings for specific objects keyed by the 3MF object id
+    pub objects: HashMap<usize, PartialSettings>,
+
+    ///Temperatures for every layer of specific objects keyed by the 3MF object id
+    pub object_temperatures: HashMap<usize, ProjectTemperatures>,
+
+    ///Warnings for settings that could not be mapped
+    pub warnings: Vec<SlicerWarnings>,
+
+    ///The complete settings if the file was written by this slicer
+    pub complete_settings: Option<Settings>,
+}
+
+impl ProjectSettings {
+    ///Apply the settings for every object on top of the settings
+    pub fn apply_to(&self, settings: &Settings) -> Result<Settings, SlicerErrors> {
+        let mut settings = settings.with_overrides(self.settings.clone())?;
+        self.temperatures.apply_to(&mut settings.filament);
+        Ok(settings)
+    }
+
+    ///The overrides of each object keyed by the 3MF object id.
+    ///The settings are the settings the overrides will be applied to
+    pub fn object_overrides(&self, settings: &Settings) -> HashMap<usize, PartialSettings> {
+        let mut objects = self.objects.clone();
+
+        for (id, temperatures) in &self.object_temperatures {
+            let object = objects.entry(*id).or_default();
+            let mut filament = object
+                .filament
+                .clone()
+                .unwrap_or_else(|| settings.filament.clone());
+            temperatures.apply_to(&mut filament);
+            object.filament = Some(filament);
+        }
+
+        objects
+    }
+}
+
+///Temperatures a project file sets for every layer.
+///These replace the filament temperatures so layer ranges, such as the first layer, still take precedence
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ProjectTemperatures {
+    ///Extruder temperature
+    pub extruder_temp: Option<f64>,
+
+    ///Bed temperature
+    pub bed_temp: Option<f64>,
+}
+
+impl ProjectTemperatures {
+    ///Combine with other temperatures, the temperatures in self take precedence
+    pub fn combine(self, other: ProjectTemperatures) -> ProjectTemperatures {
+        ProjectTemperatures {
+            extruder_temp: self.extruder_temp.or(other.extruder_temp),
+            bed_temp: self.bed_temp.or(other.bed_temp),
+        }
+    }
+
+    ///Replace the filament temperatures that are set
+    pub fn apply_to(&self, filament: &mut FilamentSettings) {
+        if let Some(extruder_temp) = self.extruder_temp {
+            filament.extruder_temp = extruder_temp;
+        }
+        if let Some(bed_temp) = self.bed_temp {
+            filament.bed_temp = bed_temp;
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        self.extruder_temp.is_some() || self.bed_temp.is_some()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ConfigFile {
+    #[serde(default)]
+    object: Vec<ConfigObject>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConfigObject {
+    id: usize,
+    #[serde(default)]
+    metadata: Vec<ConfigMetadata>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConfigMetadata {
+    key: String,
+    value: String,
+}
+
+impl ThreeMFLoader {
+    ///Load the slicer settings stored in the `Metadata/*.config` parts of a 3MF project file.
+    ///Prusa style `key = value` parts, Bambu style JSON parts and per object XML parts are supported.
+    pub fn load_project_settings(&self, filepath: &str) -> Result<ProjectSettings, SlicerErrors> {
+        let zipfile =
+            std::fs::File::open(filepath).map_err(|_| SlicerErrors::ObjectFileNotFound {
+                filepath: filepath.to_string(),
+            })?;
+
+        let mut archive =
+            zip::ZipArchive::new(zipfile).map_err(|_| SlicerErrors::ThreemfUnsupportedType)?;
+
+        let mut parts: Vec<String> = archive
+            .file_names()
+            .filter(|name| {
+                let name = name.to_lowercase();
+                name.starts_with("metadata/") && name.ends_with(".config")
+            })
+            .map(|name| name.to_string())
+            .collect();
+        parts.sort();
+
+        let mut project = ProjectSettings::default();
+
+        for part in parts {
+            let mut text = String::new();
+            archive
+                .by_name(&part)
+                .map_err(|err| config_error(filepath, &part, err.to_string()))?
+                .read_to_string(&mut text)
+                .map_err(|err| config_error(filepath, &part, err.to_string()))?;
+
+            let trimmed = text.trim_start();
+            if trimmed.starts_with('<') {
+                //Per object settings
+                let mut deserializer =
+                    serde_xml_rs::Deserializer::new_from_reader(trimmed.as_bytes())
+                        .non_contiguous_seq_elements(true);
+                let config = ConfigFile::deserialize(&mut deserializer)
+                    .map_err(|err| config_error(filepath, &part, err.to_string()))?;
+
+                for object in config.object {
+                    let mut mapper = SettingsMapper::default();
+                    for metadata in &object.metadata {
+                        mapper.apply(&metadata.key, &metadata.value);
+                    }
+
+                    let mapped = mapper.mapped;
+                    let (settings, temperatures, warning) =
+                        mapper.finish(format!("{} object id=\"{}\"", part, object.id));
+                    project.warnings.extend(warning);
+
+                    if mapped > 0 {
+                        let existing = project.objects.remove(&object.id).unwrap_or_default();
+                        project
+                            .objects
+                            .insert(object.id, settings.combine(existing));
+                    }
+
+                    if temperatures.is_set() {
+                        let existing = project
+                            .object_temperatures
+                            .remove(&object.id)
+                            .unwrap_or_default();
+                        project
+                            .object_temperatures
+                            .insert(object.id, temperatures.combine(existing));
+                    }
+                }
+            } else {
+                let mut mapper = SettingsMapper::default();
+
+                if trimmed.starts_with('{') {
+                    //Bambu style json where values are strings or arrays of strings per extruder
+                    let values: HashMap<String, serde_json::Value> = serde_json::from_str(trimmed)
+                        .map_err(|err| config_error(filepath, &part, err.to_string()))?;
+
+                    let mut keys: Vec<&String> = values.keys().collect();
+                    keys.sort();
+
+                    for key in keys {
+                        match json_value_to_string(&values[key]) {
+                            Some(value) => mapper.apply(key, &value),
+                            None => mapper.unmapped.push(key.clone()),
+                        }
+                    }
+                } else {
+                    //Prusa style lines of "; key = value" with comma separated values per extruder
+                    for line in trimmed.lines() {
+                        let line = line.trim_start_matches(';').trim();
+                        if let Some((key, value)) = line.split_once('=') {
+                            let first_value = value.split(',').next().unwrap_or(value);
+                            mapper.apply(key.trim(), first_value.trim());
+                        }
+                    }
+                }
+
+                let (settings, temperatures, warning) = mapper.finish(part);
+                project.warnings.extend(warning);
+                project.settings = settings.combine(project.settings);
+                project.temperatures = temperatures.combine(project.temperatures);
+            }
+        }
+
+        //Settings written by this slicer reproduce the original slice
+        if let Ok(mut file) = archive.by_name(SETTINGS_PART) {
+            let mut text = String::new();
+            file.read_to_string(&mut text)
+                .map_err(|err| config_error(filepath, SETTINGS_PART, err.to_string()))?;
+            project.complete_settings = Some(
+                serde_json::from_str(&text)
+                    .map_err(|err| config_error(filepath, SETTINGS_PART, err.to_string()))?,
+            );
+        }
+
+        if let Ok(mut file) = archive.by_name(OBJECT_SETTINGS_PART) {
+            let mut text = String::new();
+            file.read_to_string(&mut text)
+                .map_err(|err| config_error(filepath, OBJECT_SETTINGS_PART, err.to_string()))?;
+            let objects: HashMap<usize, PartialSettings> = serde_json::from_str(&text)
+                .map_err(|err| config_error(filepath, OBJECT_SETTINGS_PART, err.to_string()))?;
+            for (id, settings) in objects {
+                let existing = project.objects.remove(&id).unwrap_or_default();
+                project.objects.insert(id, settings.combine(existing));
+            }
+        }
+
+        Ok(project)
+    }
+}
+
+fn config_error(filepath: &str, part: &str, reason: String) -> SlicerErrors {
+    SlicerErrors::ThreemfLoadError {
+        filepath: filepath.to_string(),
+        element: part.to_string(),
+        reason,
+    }
+}
+
+fn json_value_to_string(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(str) => Some(str.clone()),
+        serde_json::Value::Number(number) => Some(number.to_string()),
+        serde_json::Value::Array(values) => values.first().and_then(json_value_to_string),
+        _ => None,
+    }
+}
+
+///Parse a number that may be a percentage into a fraction
+fn parse_fraction(value: &str) -> Option<f64> {
+    match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().ok().map(|p| p / 100.0),
+        None => value.parse().ok(),
+    }
+}
+
+///Maps the setting names used by other slicers onto the slicers settings
+#[derive(Default)]
+struct SettingsMapper {
+    settings: PartialSettings,
+    first_layer: PartialLayerSettings,
+    all_layers: ProjectTemperatures,
+    first_layer_height_fraction: Option<f64>,
+    mapped: usize,
+    unmapped: Vec<String>,
+}
+
+impl SettingsMapper {
+    fn apply(&mut self, key: &str, value: &str) {
+        let number = value.parse::<f64>().ok();
+
+        let mapped = match key {
+            "layer_height" => number.map(|v| self.settings.layer_height = Some(v)),
+            "first_layer_height" | "initial_layer_print_height" => {
+                if value.ends_with('%') {
+                    parse_fraction(value).map(|v| self.first_layer_height_fraction = Some(v))
+                } else {
+                    number.map(|v| self.first_layer.layer_height = Some(v))
+                }
+            }
+            "perimeters" | "wall_loops" => value
+                .parse()
+                .ok()
+                .map(|v| self.settings.number_of_perimeters = Some(v)),
+            "top_solid_layers" | "top_shell_layers" => value
+                .parse()
+                .ok()
+                .map(|v| self.settings.top_layers = Some(v)),
+            "bottom_solid_layers" | "bottom_shell_layers" => value
+                .parse()
+                .ok()
+                .map(|v| self.settings.bottom_layers = Some(v)),
+            "spiral_vase" | "spiral_mode" => match value {
+                "1" | "true" => Some(true),
+                "0" | "false" => Some(false),
+                _ => None,
+            }
+            .map(|v| self.settings.spiral_vase = Some(v)),
+            "fill_density" | "sparse_infill_density" => {
+                parse_fraction(value).map(|v| self.settings.infill_percentage = Some(v))
+            }
+            "temperature" | "nozzle_temperature" => {
+                number.map(|v| self.all_layers.extruder_temp = Some(v))
+            }
+            "first_layer_temperature" | "nozzle_temperature_initial_layer" => {
+                number.map(|v| self.first_layer.extruder_temp = Some(v))
+            }
+            "bed_temperature" | "hot_plate_temp" => {
+                number.map(|v| self.all_layers.bed_temp = Some(v))
+            }
+            "first_layer_bed_temperature" | "hot_plate_temp_initial_layer" => {
+                number.map(|v| self.first_layer.bed_temp = Some(v))
+            }
+            "nozzle_diameter" => number.map(|v| self.settings.nozzle_diameter = Some(v)),
+            "retract_length" | "retraction_length" => {
+                number.map(|v| self.settings.retract_length = Some(v))
+            }
+            "brim_width" => number.map(|v| {
+                //A width of zero disables the brim which is the default
+                if v > 0.0 {
+                    self.settings.brim_width = Some(v)
+                }
+            }),
+            //The object name is already read from the model
+            "name" => return,
+            _ => None,
+        };
+
+        if mapped.is_some() {
+            self.mapped += 1;
+        } else {
+            self.unmapped.push(key.to_string());
+        }
+    }
+
+    ///Convert into the settings, the temperatures for every layer and a warning listing any settings that could not be mapped
+    fn finish(
+        mut self,
+        part: String,
+    ) -> (PartialSettings, ProjectTemperatures, Option<SlicerWarnings>) {
+        //Percentage first layer heights are relative to the layer height in the same part
+        if let Some(fraction) = self.first_layer_height_fraction {
+            match self.settings.layer_height {
+                Some(layer_height) => self.first_layer.layer_height = Some(layer_height * fraction),
+                None => self.unmapped.push("first_layer_height".to_string()),
+            }
+        }
+
+        if is_set(&self.first_layer) {
+            self.settings.layer_settings =
+                Some(vec![(LayerRange::SingleLayer(0), self.first_layer)]);
+        }
+
+        let warning = if self.unmapped.is_empty() {
+            None
+        } else {
+            Some(SlicerWarnings::UnsupportedProjectSettings {
+                part,
+                settings: self.unmapped,
+            })
+        };
+
+        (self.settings, self.all_layers, warning)
+    }
+}
+
+fn is_set(layer_settings: &PartialLayerSettings) -> bool {
+    layer_settings.layer_height.is_some()
+        || layer_settings.extruder_temp.is_some()
+        || layer_settings.bed_temp.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn load_project(name: &str, parts: &[(&str, &str)]) -> ProjectSettings {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        for (part, contents) in parts {
+            zip.start_file(*part, zip::write::SimpleFileOptions::default())
+                .expect("Zip is writable");
+            zip.write_all(contents.as_bytes()).expect("Zip is writable");
+        }
+        let data = zip.finish().expect("Zip is writable").into_inner();
+
+        ThreeMFLoader {}
+            .load_project_settings(&write_test_file(name, &data))
+            .expect("Valid project file")
+    }
+
+    fn first_layer(project: &ProjectSettings) -> &PartialLayerSettings {
+        match project.settings.layer_settings.as_deref() {
+            Some([(LayerRange::SingleLayer(0), first_layer)]) => first_layer,
+            other => panic!("Unexpected layer settings {:?}", other),
+        }
+    }
+
+    #[test]
+    fn prusa_settings_are_mapped() {
+        let project = load_project(
+            "prusa.3mf",
+            &[(
+                "Metadata/Slic3r_PE.config",
+                "; layer_height = 0.3\n; first_layer_height = 50%\n; perimeters = 4\n; fill_density = 15%\n; temperature = 230,240\n; first_layer_temperature = 235\n; unknown_setting = 1\n",
+            )],
+        );
+
+        assert_eq!(project.settings.layer_height, Some(0.3));
+        assert_eq!(project.settings.number_of_perimeters, Some(4));
+        assert_eq!(project.settings.infill_percentage, Some(0.15));
+
+        //Percentages are relative to the layer height and the first extruder is used
+        assert_eq!(first_layer(&project).layer_height, Some(0.15));
+        assert_eq!(first_layer(&project).extruder_temp, Some(235.0));
+        assert_eq!(project.temperatures.extruder_temp, Some(230.0));
+
+        assert_eq!(
+            project.warnings,
+            vec![SlicerWarnings::UnsupportedProjectSettings {
+                part: "Metadata/Slic3r_PE.config".to_string(),
+                settings: vec!["unknown_setting".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn bambu_settings_are_mapped() {
+        let project = load_project(
+            "bambu.3mf",
+            &[(
+                "Metadata/project_settings.config",
+                r#"{"layer_height": "0.2", "initial_layer_print_height": "0.25", "wall_loops": "2", "sparse_infill_density": "20%", "nozzle_temperature": ["220", "225"], "hot_plate_temp": ["60"], "spiral_mode": "1"}"#,
+            )],
+        );
+
+        assert_eq!(project.settings.layer_height, Some(0.2));
+        assert_eq!(project.settings.number_of_perimeters, Some(2));
+        assert_eq!(project.settings.infill_percentage, Some(0.2));
+        assert_eq!(project.settings.spiral_vase, Some(true));
+        assert_eq!(first_layer(&project).layer_height, Some(0.25));
+        assert_eq!(
+            project.temperatures,
+            ProjectTemperatures {
+                extruder_temp: Some(220.0),
+                bed_temp: Some(60.0),
+            }
+        );
+        assert!(project.warnings.is_empty());
+    }
+
+    #[test]
+    fn first_layer_settings_take_precedence_over_project_temperatures() {
+        let settings = Settings {
+            layer_settings: vec![(
+                LayerRange::SingleLayer(0),
+                PartialLayerSettings {
+                    extruder_temp: Some(215.0),
+                    ..Default::default()
+                },
+            )],
+            ..Default::default()
+        };
+
+        let project = load_project(
+            "temperature.3mf",
+            &[
+                ("Metadata/Slic3r_PE.config", "; temperature = 230\n"),
+                (
+                    "Metadata/Slic3r_PE_model.config",
+                    r#"<?xml version="1.0"?><config><object id="1"><metadata type="object" key="temperature" value="250"/></object></config>"#,
+                ),
+            ],
+        );
+
+        //The settings file first layer is kept as the project has no first layer temperature
+        let applied = project.apply_to(&settings).expect("Valid settings");
+        assert_eq!(applied.get_layer_settings(0, 0.1).extruder_temp, 215.0);
+        assert_eq!(applied.get_layer_settings(5, 1.0).extruder_temp, 230.0);
+
+        let object = applied
+            .with_overrides(project.object_overrides(&applied)[&1].clone())
+            .expect("Valid settings");
+        assert_eq!(object.get_layer_settings(0, 0.1).extruder_temp, 215.0);
+        assert_eq!(object.get_layer_settings(5, 1.0).extruder_temp, 250.0);
+
+        //A project first layer temperature takes precedence over the settings file
+        let project = load_project(
+            "first_layer_temperature.3mf",
+            &[(
+                "Metadata/Slic3r_PE.config",
+                "; temperature = 230\n; first_layer_temperature = 240\n",
+            )],
+        );
+
+        let applied = project.apply_to(&settings).expect("Valid settings");
+        assert_eq!(applied.get_layer_settings(0, 0.1).extruder_temp, 240.0);
+        assert_eq!(applied.get_layer_settings(5, 1.0).extruder_temp, 230.0);
+    }
+}
@@ -1,6 +1,7 @@
 #![deny(missing_docs)]
 
 use crate::error::SlicerErrors;
+use crate::settings::PartialSettings;
 use crate::types::{CalculatedValues, Command};
 use crate::warning::SlicerWarnings;
 use serde::{Deserialize, Serialize};
@@ -20,9 +21,122 @@ pub enum Message {
     ///Message to share the current state of the slicer
     StateUpdate(String),
 
+    ///Message to share how far through a stage the slicer is
+    Progress(ProgressUpdate),
+
     ///Message to share any errors encountered
     Error(SlicerErrors),
 
     ///Message to share any warnings encountered
     Warning(SlicerWarnings),
 }
+
+///The stages of the slicer that report progress
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProgressStage {
+    ///Layers sliced from the model
+    Slicing,
+
+    ///Layers with support towers added
+    SupportTowers,
+
+    ///Layers shrunk
+    ShrinkLayers,
+
+    ///Layers with perimeters generated
+    Perimeters,
+
+    ///Layers with bridges filled
+    Bridging,
+
+    ///Layers with top layers filled
+    TopLayer,
+
+    ///Layers with solid areas above and below filled
+    TopAndBottomLayers,
+
+    ///Layers with support filled
+    Support,
+
+    ///Layers with remaining areas filled
+    FillAreas,
+
+    ///Layers with move chains ordered
+    OrderChains,
+
+    ///Objects with all moves generated
+    Objects,
+
+    ///Commands converted to G-code
+    Converting,
+}
+
+///Progress through a stage of the slicer
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProgressUpdate {
+    ///The stage progressing
+    pub stage: ProgressStage,
+
+    ///The index of the object progressing, None for stages that cover every object
+    pub object: Option<usize>,
+
+    ///Units of work completed
+    pub completed: usize,
+
+    ///Units of work in the stage
+    pub total: usize,
+}
+
+///Requests sent to a slicer running as a server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    ///Load and place the models using the current settings, inputs use the command line format
+    LoadModels {
+        ///The inputs to load
+        inputs: Vec<String>,
+
+        ///Use the slicer settings stored in 3MF project files
+        project_settings: bool,
+
+        ///Arrange all automatically placed models on the build plate
+        arrange: bool,
+
+        ///Allow models to be rotated around z when arranging them
+        arrange_rotate: bool,
+    },
+
+    ///Load the settings from a settings file
+    LoadSettings(String),
+
+    ///Replace the settings, the partial settings must resolve to complete settings
+    SetSettings(Box<PartialSettings>),
+
+    ///Slice the loaded models, the G-code is written to the file if provided otherwise sent as a message
+    Slice(Option<String>),
+
+    ///Cancel the request with the provided id if it has not started
+    Cancel(u64),
+
+    ///Stop the server
+    Shutdown,
+}
+
+///A request and the id used to correlate its responses
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestMessage {
+    ///Id of the request, chosen by the sender
+    pub id: u64,
+
+    ///The request
+    pub request: Request,
+}
+
+///A message sent by a slicer running as a server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResponseMessage {
+    ///Id of the request this message responds to
+    pub id: u64,
+
+    ///The message
+    pub message: Message,
+}
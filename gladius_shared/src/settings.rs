@@ -4,6 +4,7 @@ use crate::error::SlicerErrors;
 use crate::types::{MoveType, PartialInfillTypes, SolidInfillTypes};
 use crate::warning::SlicerWarnings;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 macro_rules! setting_less_than_or_equal_to_zero {
     ($settings:ident,$setting:ident) => {{
@@ -58,11 +59,14 @@ macro_rules! option_setting_less_than_zero {
 }
 
 ///A complete settings file for the entire slicer.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Settings {
     ///The height of the layers
     pub layer_height: f64,
 
+    ///Vary the layer height with the slope of the model, if None the layer height is constant
+    pub adaptive_layer_height: Option<AdaptiveLayerHeightSettings>,
+
     ///The extrusion width of the layers
     pub extrusion_width: MovementParameter,
 
@@ -114,6 +118,9 @@ pub struct Settings {
     ///Number of solid bottom layers before infill
     pub bottom_layers: usize,
 
+    ///Print a single exterior perimeter above the bottom layers with z rising continuously
+    pub spiral_vase: bool,
+
     ///Size of the printer in x dimension in mm
     pub print_x: f64,
 
@@ -202,9 +209,11 @@ impl Default for Settings {
     fn default() -> Self {
         Settings {
             layer_height: 0.15,
+            adaptive_layer_height: None,
             number_of_perimeters: 3,
             top_layers: 3,
             bottom_layers: 3,
+            spiral_vase: false,
             extrusion_width: MovementParameter {
                 interior_inner_perimeter: 0.4,
                 interior_surface_perimeter: 0.4,
@@ -335,10 +344,9 @@ impl Default for Settings {
 }
 
 impl Settings {
-    ///Get the layer settings for a specific layer index and height
-    pub fn get_layer_settings(&self, layer: usize, height: f64) -> LayerSettings {
-        let changes = self
-            .layer_settings
+    ///Get the changes from the layer settings that apply to a specific layer index and height
+    pub fn get_layer_changes(&self, layer: usize, height: f64) -> PartialLayerSettings {
+        self.layer_settings
             .iter()
             .filter(|(layer_range, _)| match layer_range {
                 LayerRange::LayerCountRange { end, start } => *start <= layer && layer <= *end,
@@ -346,7 +354,18 @@ impl Settings {
                 LayerRange::SingleLayer(filter_layer) => *filter_layer == layer,
             })
             .map(|(_lr, pls)| pls)
-            .fold(PartialLayerSettings::default(), |a, b| a.combine(b));
+            .fold(PartialLayerSettings::default(), |a, b| a.combine(b))
+    }
+
+    ///Whether the layer is printed as a single spiral perimeter in spiral vase mode
+    pub fn is_spiral_layer(&self, layer: usize) -> bool {
+        //The first layer is always solid so the vase has a bottom to build on
+        self.spiral_vase && layer >= self.bottom_layers.max(1)
+    }
+
+    ///Get the layer settings for a specific layer index and height
+    pub fn get_layer_settings(&self, layer: usize, height: f64) -> LayerSettings {
+        let changes = self.get_layer_changes(layer, height);
 
         LayerSettings {
             layer_height: changes.layer_height.unwrap_or(self.layer_height),
@@ -378,6 +397,13 @@ impl Settings {
         }
     }
 
+    ///Apply the partial settings on top of these settings, the partial settings take precedence
+    pub fn with_overrides(&self, overrides: PartialSettings) -> Result<Settings, SlicerErrors> {
+        overrides
+            .combine(PartialSettings::from(self))
+            .get_settings()
+    }
+
     ///Validate settings and return any warnings and errors
     pub fn validate_settings(&self) -> SettingsValidationResult {
         setting_less_than_or_equal_to_zero!(self, print_x);
@@ -423,6 +449,24 @@ impl Settings {
             });
         }
 
+        if let Some(adaptive) = self.adaptive_layer_height.as_ref() {
+            setting_less_than_or_equal_to_zero!(adaptive, min_layer_height);
+            setting_less_than_or_equal_to_zero!(adaptive, max_layer_height);
+            setting_less_than_zero!(adaptive, quality);
+
+            if adaptive.min_layer_height < self.nozzle_diameter * 0.2 {
+                return SettingsValidationResult::Warning(SlicerWarnings::LayerSizeTooLow {
+                    layer_height: adaptive.min_layer_height,
+                    nozzle_diameter: self.nozzle_diameter,
+                });
+            } else if adaptive.max_layer_height > self.nozzle_diameter * 0.8 {
+                return SettingsValidationResult::Warning(SlicerWarnings::LayerSizeTooHigh {
+                    layer_height: adaptive.max_layer_height,
+                    nozzle_diameter: self.nozzle_diameter,
+                });
+            }
+        }
+
         let r = check_extrusions(&self.extrusion_width, self.nozzle_diameter);
         match r {
             SettingsValidationResult::NoIssue => {}
@@ -535,6 +579,7 @@ pub enum SettingsValidationResult {
 }
 
 ///Settings specific to a Layer
+#[derive(Clone)]
 pub struct LayerSettings {
     ///The height of the layers
     pub layer_height: f64,
@@ -698,6 +743,19 @@ pub struct SupportSettings {
     pub support_spacing: f64,
 }
 
+///Adaptive layer height settings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdaptiveLayerHeightSettings {
+    ///The thinnest layer used on shallow slopes in mm
+    pub min_layer_height: f64,
+
+    ///The thickest layer used on vertical walls in mm
+    pub max_layer_height: f64,
+
+    ///From 0 to 1, higher values use thinner layers for a smoother surface
+    pub quality: f64,
+}
+
 ///The Settings for Skirt generation
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SkirtSettings {
@@ -722,11 +780,14 @@ pub struct RetractionWipeSettings {
 }
 
 ///A partial complete settings file
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct PartialSettings {
     ///The height of the layers
     pub layer_height: Option<f64>,
 
+    ///Vary the layer height with the slope of the model
+    pub adaptive_layer_height: Option<AdaptiveLayerHeightSettings>,
+
     ///The extrusion width of the layers
     pub extrusion_width: Option<MovementParameter>,
 
@@ -776,6 +837,9 @@ pub struct PartialSettings {
     ///Number of solid bottom layers before infill
     pub bottom_layers: Option<usize>,
 
+    ///Print a single exterior perimeter above the bottom layers with z rising continuously
+    pub spiral_vase: Option<bool>,
+
     ///Size of the printer in x dimension in mm
     pub print_x: Option<f64>,
 
@@ -863,8 +927,14 @@ pub struct PartialSettings {
 impl PartialSettings {
     ///Convert a partial settings file into a complete settings file
     /// returns an error if a settings is not present in this or any sub file
-    pub fn get_settings(mut self) -> Result<Settings, SlicerErrors> {
-        self.combine_with_other_files()?;
+    pub fn get_settings(self) -> Result<Settings, SlicerErrors> {
+        self.get_settings_relative_to(Path::new(""))
+    }
+
+    ///Convert a partial settings file into a complete settings file, with the other files
+    /// relative to the directory. Returns an error if a settings is not present in this or any sub file
+    pub fn get_settings_relative_to(mut self, directory: &Path) -> Result<Settings, SlicerErrors> {
+        self.combine_with_other_files(directory)?;
 
         try_convert_partial_to_settings(self).map_err(|err| {
             SlicerErrors::SettingsFileMissingSettings {
@@ -873,7 +943,8 @@ impl PartialSettings {
         })
     }
 
-    fn combine_with_other_files(&mut self) -> Result<(), SlicerErrors> {
+    ///Load the other files, each relative to the directory of the file that lists it
+    fn combine_with_other_files(&mut self, directory: &Path) -> Result<(), SlicerErrors> {
         let files: Vec<String> = self
             .other_files
             .as_mut()
@@ -881,17 +952,18 @@ impl PartialSettings {
             .unwrap_or_default();
 
         for file in &files {
+            let path = directory.join(file);
             let mut ps: PartialSettings =
-                deser_hjson::from_str(&std::fs::read_to_string(file).map_err(|_| {
+                deser_hjson::from_str(&std::fs::read_to_string(&path).map_err(|_| {
                     SlicerErrors::SettingsRecursiveLoadError {
-                        filepath: file.to_string(),
+                        filepath: path.display().to_string(),
                     }
                 })?)
                 .map_err(|_| SlicerErrors::SettingsFileMisformat {
-                    filepath: file.to_string(),
+                    filepath: path.display().to_string(),
                 })?;
 
-            ps.combine_with_other_files()?;
+            ps.combine_with_other_files(path.parent().unwrap_or(directory))?;
 
             *self = self.combine(ps);
         }
@@ -899,9 +971,14 @@ impl PartialSettings {
         Ok(())
     }
 
-    fn combine(&self, other: PartialSettings) -> PartialSettings {
+    ///Combine with other partial settings, the settings in self take precedence
+    pub fn combine(&self, other: PartialSettings) -> PartialSettings {
         PartialSettings {
             layer_height: self.layer_height.or(other.layer_height),
+            adaptive_layer_height: self
+                .adaptive_layer_height
+                .clone()
+                .or_else(|| other.adaptive_layer_height.clone()),
             extrusion_width: self
                 .extrusion_width
                 .clone()
@@ -924,6 +1001,7 @@ impl PartialSettings {
             infill_percentage: self.infill_percentage.or(other.infill_percentage),
             inner_perimeters_first: self.inner_perimeters_first.or(other.inner_perimeters_first),
             number_of_perimeters: self.number_of_perimeters.or(other.number_of_perimeters),
+            spiral_vase: self.spiral_vase.or(other.spiral_vase),
             top_layers: self.top_layers.or(other.top_layers),
             bottom_layers: self.bottom_layers.or(other.bottom_layers),
             print_x: self.print_x.or(other.print_x),
@@ -1000,6 +1078,68 @@ impl PartialSettings {
     }
 }
 
+impl From<&Settings> for PartialSettings {
+    fn from(settings: &Settings) -> Self {
+        PartialSettings {
+            layer_height: Some(settings.layer_height),
+            adaptive_layer_height: settings.adaptive_layer_height.clone(),
+            extrusion_width: Some(settings.extrusion_width.clone()),
+            filament: Some(settings.filament.clone()),
+            fan: Some(settings.fan.clone()),
+            skirt: settings.skirt.clone(),
+            support: settings.support.clone(),
+            nozzle_diameter: Some(settings.nozzle_diameter),
+            retract_length: Some(settings.retract_length),
+            retract_lift_z: Some(settings.retract_lift_z),
+            retract_speed: Some(settings.retract_speed),
+            retraction_wipe: settings.retraction_wipe.clone(),
+            speed: Some(settings.speed.clone()),
+            acceleration: Some(settings.acceleration.clone()),
+            infill_percentage: Some(settings.infill_percentage),
+            inner_perimeters_first: Some(settings.inner_perimeters_first),
+            number_of_perimeters: Some(settings.number_of_perimeters),
+            spiral_vase: Some(settings.spiral_vase),
+            top_layers: Some(settings.top_layers),
+            bottom_layers: Some(settings.bottom_layers),
+            print_x: Some(settings.print_x),
+            print_y: Some(settings.print_y),
+            print_z: Some(settings.print_z),
+            brim_width: settings.brim_width,
+            layer_shrink_amount: settings.layer_shrink_amount,
+            minimum_retract_distance: Some(settings.minimum_retract_distance),
+            infill_perimeter_overlap_percentage: Some(settings.infill_perimeter_overlap_percentage),
+            solid_infill_type: Some(settings.solid_infill_type),
+            partial_infill_type: Some(settings.partial_infill_type),
+            starting_instructions: Some(settings.starting_instructions.clone()),
+            ending_instructions: Some(settings.ending_instructions.clone()),
+            before_layer_change_instructions: Some(
+                settings.before_layer_change_instructions.clone(),
+            ),
+            after_layer_change_instructions: Some(settings.after_layer_change_instructions.clone()),
+            object_change_instructions: Some(settings.object_change_instructions.clone()),
+            other_files: None,
+            max_acceleration_x: Some(settings.max_acceleration_x),
+            max_acceleration_y: Some(settings.max_acceleration_y),
+            max_acceleration_z: Some(settings.max_acceleration_z),
+            max_acceleration_e: Some(settings.max_acceleration_e),
+            max_acceleration_extruding: Some(settings.max_acceleration_extruding),
+            max_acceleration_travel: Some(settings.max_acceleration_travel),
+            max_acceleration_retracting: Some(settings.max_acceleration_retracting),
+            max_jerk_x: Some(settings.max_jerk_x),
+            max_jerk_y: Some(settings.max_jerk_y),
+            max_jerk_z: Some(settings.max_jerk_z),
+            max_jerk_e: Some(settings.max_jerk_e),
+            minimum_feedrate_print: Some(settings.minimum_feedrate_print),
+            minimum_feedrate_travel: Some(settings.minimum_feedrate_travel),
+            maximum_feedrate_x: Some(settings.maximum_feedrate_x),
+            maximum_feedrate_y: Some(settings.maximum_feedrate_y),
+            maximum_feedrate_z: Some(settings.maximum_feedrate_z),
+            maximum_feedrate_e: Some(settings.maximum_feedrate_e),
+            layer_settings: Some(settings.layer_settings.clone()),
+        }
+    }
+}
+
 /// The different types of layer ranges supported
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum LayerRange {
@@ -1108,6 +1248,7 @@ impl PartialLayerSettings {
 fn try_convert_partial_to_settings(part: PartialSettings) -> Result<Settings, String> {
     Ok(Settings {
         layer_height: part.layer_height.ok_or("layer_height")?,
+        adaptive_layer_height: part.adaptive_layer_height,
         extrusion_width: part.extrusion_width.ok_or("extrusion_width")?,
         filament: part.filament.ok_or("filament")?,
         fan: part.fan.ok_or("fan")?,
@@ -1127,6 +1268,7 @@ fn try_convert_partial_to_settings(part: PartialSettings) -> Result<Settings, St
         number_of_perimeters: part.number_of_perimeters.ok_or("number_of_perimeters")?,
         top_layers: part.top_layers.ok_or("top_layers")?,
         bottom_layers: part.bottom_layers.ok_or("bottom_layers")?,
+        spiral_vase: part.spiral_vase.unwrap_or_default(),
         print_x: part.print_x.ok_or("print_x")?,
         print_y: part.print_y.ok_or("print_y")?,
         print_z: part.print_z.ok_or("print_z")?,
@@ -1,15 +1,17 @@
 #![deny(missing_docs)]
 
 use crate::error::SlicerErrors;
-use crate::settings::{LayerSettings, Settings};
+use crate::settings::{LayerSettings, PartialSettings, Settings};
 use geo::contains::Contains;
 use geo::*;
 use itertools::Itertools;
 use nalgebra::Point3;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
 ///A single slice of an object containing it's current plotting status.
+#[derive(Clone)]
 pub struct Slice {
     ///The slice's entire polygon. Should not be modified after creation by the slicing process.
     pub main_polygon: MultiPolygon<f64>,
@@ -52,9 +54,12 @@ impl Slice {
     {
         let polygon = Polygon::new(LineString::from_iter(line), vec![]);
 
-        let layer_settings =
+        let mut layer_settings =
             settings.get_layer_settings(layer_count, (bottom_height + top_height) / 2.0);
 
+        //The layer height can be chosen while slicing so use the actual height
+        layer_settings.layer_height = top_height - bottom_height;
+
         Slice {
             main_polygon: MultiPolygon(vec![polygon.simplify_vw_preserve(&0.01)]),
             remaining_area: MultiPolygon(vec![polygon]),
@@ -104,16 +109,31 @@ impl Slice {
                     .iter_mut()
                     .rev()
                     .find(|poly| poly.contains(&line.0[0]))
-                    .ok_or(SlicerErrors::SliceGeneration)?;
+                    .ok_or_else(|| SlicerErrors::SliceGeneration {
+                        layer: layer_count,
+                        height: (bottom_height + top_height) / 2.0,
+                        points: line
+                            .0
+                            .iter()
+                            .map(|coord| Vertex {
+                                x: coord.x,
+                                y: coord.y,
+                                z: (bottom_height + top_height) / 2.0,
+                            })
+                            .collect(),
+                    })?;
                 smallest_polygon.interiors_push(line);
             }
         }
 
         let multi_polygon: MultiPolygon<f64> = MultiPolygon(polygons);
 
-        let layer_settings =
+        let mut layer_settings =
             settings.get_layer_settings(layer_count, (bottom_height + top_height) / 2.0);
 
+        //The layer height can be chosen while slicing so use the actual height
+        layer_settings.layer_height = top_height - bottom_height;
+
         Ok(Slice {
             main_polygon: multi_polygon.clone(),
             remaining_area: multi_polygon.simplify_vw(&0.0001),
@@ -141,6 +161,15 @@ pub enum SolidInfillTypes {
 
     ///Back and forth lines to fill polygons, rotating custom degrees each layer
     RectilinearCustom(f64),
+
+    ///Loops following the outline of the polygons, each inset from the last
+    Concentric,
+
+    ///A single Hilbert curve clipped to the polygons, giving a textured surface
+    HilbertCurve,
+
+    ///A single spiral from the center of each polygon made of short straight chords
+    ArchimedeanChords,
 }
 
 ///Types of partial infill
@@ -158,12 +187,27 @@ pub enum PartialInfillTypes {
     /// Creates a 3d cube structure.
     Cubic,
 
+    /// Cubic infill where the cubes grow larger away from the walls and top surfaces
+    AdaptiveCubic,
+
     ///Creates lightning shaped infill that retracts into the print walls
     Lightning,
+
+    ///Curved walls following the cross section of a gyroid, shifting each layer
+    Gyroid,
+
+    ///Hexagonal cells that are the same on every layer
+    Honeycomb,
+
+    ///Cells of stacked truncated octahedrons, the cross section changes with the height
+    Honeycomb3D,
+
+    ///Loops following the outline of the polygons, spaced to give the infill percentage
+    Concentric,
 }
 
 ///A single 3D vertex
-#[derive(Default, Clone, Copy, Debug, PartialEq, Deserialize)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename = "vertex")]
 pub struct Vertex {
     ///X Coord
@@ -220,25 +264,88 @@ impl Transform {
             [0., 0., 0., 1.],
         ])
     }
+
+    ///create a new transform for scaling along each axis
+    pub fn new_scale_transform(x: f64, y: f64, z: f64) -> Self {
+        Transform([
+            [x, 0., 0., 0.],
+            [0., y, 0., 0.],
+            [0., 0., z, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    ///create a new transform for rotation in radians, applied around X then Y then Z
+    pub fn new_rotation_transform(x: f64, y: f64, z: f64) -> Self {
+        let rotation = nalgebra::Rotation3::from_euler_angles(x, y, z);
+        let m = rotation.matrix();
+        Transform([
+            [m[(0, 0)], m[(0, 1)], m[(0, 2)], 0.],
+            [m[(1, 0)], m[(1, 1)], m[(1, 2)], 0.],
+            [m[(2, 0)], m[(2, 1)], m[(2, 2)], 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    ///Whether the transform mirrors the model, which reverses the winding of its triangles
+    pub fn is_mirroring(&self) -> bool {
+        let m = &self.0;
+        let determinant = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+
+        determinant < 0.0
+    }
 }
 
-/*
-impl std::ops::Mul<Transform> for Transform{
+impl std::ops::Mul<&Transform> for &Transform {
     type Output = Transform;
 
-    fn mul(self, rhs: Transform) -> Self::Output {
-        let arrays = [[0.0 ; 4];4];
-        for 0..4
+    fn mul(self, rhs: &Transform) -> Self::Output {
+        let mut arrays = [[0.0; 4]; 4];
+        for (row, array_row) in arrays.iter_mut().enumerate() {
+            for (col, value) in array_row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[row][k] * rhs.0[k][col]).sum();
+            }
+        }
 
         Transform(arrays)
     }
 }
-*/
 
 ///A object is the collection of slices for a particular model.
+#[derive(Clone)]
 pub struct Object {
     /// The slices for this model sorted from lowest to highest.
     pub layers: Vec<Slice>,
+
+    /// The identity of the model this object was created from.
+    pub info: ObjectInfo,
+
+    /// The settings for this object if it overrides the global settings.
+    pub settings: Option<Settings>,
+}
+
+///The identity of a loaded model, preserved from the input file through to the commands.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ObjectInfo {
+    /// The name of the object if the file or input provides one
+    pub name: Option<String>,
+
+    /// The part number of the object if the file provides one
+    pub part_number: Option<String>,
+
+    /// The id of the object in the source file
+    pub source_id: Option<usize>,
+
+    /// The index of this instance when the source object is placed multiple times
+    pub instance: usize,
+
+    /// Any additional metadata from the source file
+    pub metadata: HashMap<String, String>,
+
+    /// Settings that override the global settings for this object
+    pub settings: Option<PartialSettings>,
 }
 
 ///The different types of input that the slicer can take.
@@ -252,6 +359,51 @@ pub enum InputObject {
 
     ///Automatically Center and raise the model for printing but offset it by x and y
     AutoTranslate(String, f64, f64),
+
+    ///Automatically place the model on the build plate so it does not overlap other arranged models
+    Arrange(String),
+
+    ///Automatically rotate the model for the goal then center and raise it for printing
+    AutoOrient(String, OrientationGoal),
+
+    ///Apply the transforms in order then center and raise the model for printing
+    Transformed(String, Vec<InputTransform>),
+}
+
+///A transform applied to an input model, described without a matrix
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum InputTransform {
+    ///Scale along the x, y and z axis
+    Scale(f64, f64, f64),
+
+    ///Scale equally along every axis
+    UniformScale(f64),
+
+    ///Rotate in degrees around the x axis then the y axis then the z axis
+    Rotate(f64, f64, f64),
+
+    ///Mirror across the plane normal to the x axis
+    MirrorX,
+
+    ///Mirror across the plane normal to the y axis
+    MirrorY,
+
+    ///Mirror across the plane normal to the z axis
+    MirrorZ,
+
+    ///Rotate so the triangle with this index lies flat on the bed.
+    ///Triangles are counted across all the models in the file in order.
+    LayFlat(usize),
+}
+
+///The goal used to choose the orientation of a model
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OrientationGoal {
+    ///Minimise the area of overhangs that need support
+    MinimalSupport,
+
+    ///Maximise the area in contact with the bed
+    MaximalContact,
 }
 
 impl InputObject {
@@ -261,6 +413,9 @@ impl InputObject {
             InputObject::Raw(str, _) => str,
             InputObject::Auto(str) => str,
             InputObject::AutoTranslate(str, _, _) => str,
+            InputObject::Arrange(str) => str,
+            InputObject::AutoOrient(str, _) => str,
+            InputObject::Transformed(str, _) => str,
         }
     }
 }
@@ -295,6 +450,7 @@ pub struct Move {
 }
 
 /// A chain of moves that should happen in order
+#[derive(Clone)]
 pub struct MoveChain {
     ///start point for the chain of moves. Needed as Moves don't contain there own start point.
     pub start_point: Coord<f64>,
@@ -347,6 +503,9 @@ pub enum Command {
     MoveTo {
         ///The end point of the move
         end: Coord<f64>,
+
+        ///The height at the end of the move, if None the height of the last layer change is kept
+        z: Option<f64>,
     },
     ///Move to a location while extruding plastic
     MoveAndExtrude {
@@ -356,6 +515,9 @@ pub enum Command {
         ///End point of the move
         end: Coord<f64>,
 
+        ///The height at the end of the move, if None the height of the last layer change is kept
+        z: Option<f64>,
+
         ///The height thickness of the move
         thickness: f64,
 
@@ -395,6 +557,9 @@ pub enum Command {
         ///The center point that the arc keeps equidistant from
         center: Coord<f64>,
 
+        ///The height at the end of the arc, rising or falling along the arc makes it helical
+        z: Option<f64>,
+
         ///Whether the arc is clockwise or anticlockwise
         clockwise: bool,
 
@@ -409,6 +574,8 @@ pub enum Command {
     ChangeObject {
         ///The index of the new object being changed to
         object: usize,
+        ///The name of the new object being changed to
+        name: Option<String>,
     },
     ///Used in optimization , should be optimized out
     NoAction,
@@ -689,12 +856,16 @@ impl MoveChain {
             }
 
             if m.move_type == MoveType::Travel {
-                cmds.push(Command::MoveTo { end: m.end });
+                cmds.push(Command::MoveTo {
+                    end: m.end,
+                    z: None,
+                });
                 current_loc = m.end;
             } else {
                 cmds.push(Command::MoveAndExtrude {
                     start: current_loc,
                     end: m.end,
+                    z: None,
                     thickness,
                     width: m.width,
                 });
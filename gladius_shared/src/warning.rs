@@ -65,6 +65,54 @@ pub enum SlicerWarnings {
         ///The extrusion width
         extrusion_width: f64,
     },
+
+    ///Near duplicate vertices were merged while repairing the model
+    VerticesWelded {
+        ///The index of the object
+        object: usize,
+        ///The number of vertices merged
+        count: usize,
+    },
+
+    ///Triangles with no area were removed while repairing the model
+    DegenerateTrianglesRemoved {
+        ///The index of the object
+        object: usize,
+        ///The number of triangles removed
+        count: usize,
+    },
+
+    ///Triangles that were repeated were removed while repairing the model
+    DuplicateTrianglesRemoved {
+        ///The index of the object
+        object: usize,
+        ///The number of triangles removed
+        count: usize,
+    },
+
+    ///Triangles facing the wrong way were flipped while repairing the model
+    TrianglesFlipped {
+        ///The index of the object
+        object: usize,
+        ///The number of triangles flipped
+        count: usize,
+    },
+
+    ///Holes in the model were closed while repairing the model
+    HolesClosed {
+        ///The index of the object
+        object: usize,
+        ///The number of holes closed
+        count: usize,
+    },
+
+    ///Settings in a 3MF project file that are not supported were ignored
+    UnsupportedProjectSettings {
+        ///The part of the 3MF file containing the settings
+        part: String,
+        ///The names of the settings that were ignored
+        settings: Vec<String>,
+    },
 }
 
 impl SlicerWarnings {
@@ -95,6 +143,24 @@ impl SlicerWarnings {
             SlicerWarnings::ExtrusionWidthTooLow { nozzle_diameter, extrusion_width } => {
                 (0x1007, format!("The provided extrusion width({} mm) is less than 60% of the nozzle diameter({} mm).", extrusion_width, nozzle_diameter))
             }
+            SlicerWarnings::VerticesWelded { object, count } => {
+                (0x1008, format!("Object {} was repaired by merging {} near duplicate vertices.", object, count))
+            }
+            SlicerWarnings::DegenerateTrianglesRemoved { object, count } => {
+                (0x1009, format!("Object {} was repaired by removing {} degenerate triangles.", object, count))
+            }
+            SlicerWarnings::DuplicateTrianglesRemoved { object, count } => {
+                (0x100A, format!("Object {} was repaired by removing {} duplicate triangles.", object, count))
+            }
+            SlicerWarnings::TrianglesFlipped { object, count } => {
+                (0x100B, format!("Object {} was repaired by flipping {} triangles with inconsistent winding.", object, count))
+            }
+            SlicerWarnings::HolesClosed { object, count } => {
+                (0x100C, format!("Object {} was repaired by closing {} holes.", object, count))
+            }
+            SlicerWarnings::UnsupportedProjectSettings { part, settings } => {
+                (0x100D, format!("The project settings in {} contain {} unsupported settings that were ignored: {}.", part, settings.len(), settings.join(", ")))
+            }
         }
     }
 }
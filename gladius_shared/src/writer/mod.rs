@@ -0,0 +1,5 @@
+#![deny(missing_docs)]
+
+mod threemf;
+
+pub use threemf::ThreeMFWriter;
@@ -0,0 +1,257 @@
+use crate::error::SlicerErrors;
+use crate::loader::{OBJECT_SETTINGS_PART, SETTINGS_PART};
+use crate::settings::{PartialSettings, Settings};
+use crate::types::*;
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use zip::write::SimpleFileOptions;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+ <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+ <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+ <Default Extension="json" ContentType="application/json"/>
+</Types>
+"#;
+
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+ <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+///Writer for 3MF files
+pub struct ThreeMFWriter {}
+
+impl ThreeMFWriter {
+    ///Write the models with their final transforms applied and the settings used to slice them.
+    ///Loading the file as a `Raw` input with the identity transform and the project settings
+    ///reproduces the slice. Other inputs, such as `Auto`, center and drop the models again.
+    pub fn write(
+        &self,
+        filepath: &str,
+        models: &[(Vec<Vertex>, Vec<IndexedTriangle>)],
+        infos: &[ObjectInfo],
+        settings: &Settings,
+    ) -> Result<(), SlicerErrors> {
+        let write_error = || SlicerErrors::FileWriteError {
+            filepath: filepath.to_string(),
+        };
+
+        let file = std::fs::File::create(filepath).map_err(|_| SlicerErrors::FileCreateError {
+            filepath: filepath.to_string(),
+        })?;
+
+        let settings_json = serde_json::to_string_pretty(settings).map_err(|_| write_error())?;
+
+        //Object ids start at 1 and follow the order of the models
+        let object_settings: HashMap<usize, &PartialSettings> = infos
+            .iter()
+            .enumerate()
+            .filter_map(|(index, info)| info.settings.as_ref().map(|s| (index + 1, s)))
+            .collect();
+        let object_settings_json =
+            serde_json::to_string_pretty(&object_settings).map_err(|_| write_error())?;
+
+        let parts = [
+            ("[Content_Types].xml", CONTENT_TYPES.to_string()),
+            ("_rels/.rels", RELATIONSHIPS.to_string()),
+            (
+                "3D/3dmodel.model",
+                model_xml(models, infos).map_err(|_| write_error())?,
+            ),
+            (SETTINGS_PART, settings_json),
+            (OBJECT_SETTINGS_PART, object_settings_json),
+        ];
+
+        let mut zip = zip::ZipWriter::new(file);
+
+        for (name, contents) in parts.iter() {
+            zip.start_file(*name, SimpleFileOptions::default())
+                .map_err(|_| write_error())?;
+            zip.write_all(contents.as_bytes())
+                .map_err(|_| write_error())?;
+        }
+
+        zip.finish().map_err(|_| write_error())?;
+
+        Ok(())
+    }
+}
+
+fn model_xml(
+    models: &[(Vec<Vertex>, Vec<IndexedTriangle>)],
+    infos: &[ObjectInfo],
+) -> Result<String, std::fmt::Error> {
+    let mut xml = String::new();
+
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        xml,
+        r#"<model unit="millimeter" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">"#
+    )?;
+    writeln!(xml, " <resources>")?;
+
+    for (index, (vertices, triangles)) in models.iter().enumerate() {
+        let info = infos.get(index);
+
+        write!(xml, r#"  <object id="{}" type="model""#, index + 1)?;
+        if let Some(name) = info.and_then(|info| info.name.as_ref()) {
+            write!(xml, r#" name="{}""#, escape_xml(name))?;
+        }
+        if let Some(part_number) = info.and_then(|info| info.part_number.as_ref()) {
+            write!(xml, r#" partnumber="{}""#, escape_xml(part_number))?;
+        }
+        writeln!(xml, ">")?;
+
+        if let Some(info) = info.filter(|info| !info.metadata.is_empty()) {
+            let mut metadata: Vec<(&String, &String)> = info.metadata.iter().collect();
+            metadata.sort();
+
+            writeln!(xml, "   <metadatagroup>")?;
+            for (name, value) in metadata {
+                writeln!(
+                    xml,
+                    r#"    <metadata name="{}">{}</metadata>"#,
+                    escape_xml(name),
+                    escape_xml(value)
+                )?;
+            }
+            writeln!(xml, "   </metadatagroup>")?;
+        }
+
+        //Rust formats floats with the shortest representation that reads back exactly
+        writeln!(xml, "   <mesh>")?;
+        writeln!(xml, "    <vertices>")?;
+        for vertex in vertices {
+            writeln!(
+                xml,
+                r#"     <vertex x="{}" y="{}" z="{}"/>"#,
+                vertex.x, vertex.y, vertex.z
+            )?;
+        }
+        writeln!(xml, "    </vertices>")?;
+        writeln!(xml, "    <triangles>")?;
+        for triangle in triangles {
+            writeln!(
+                xml,
+                r#"     <triangle v1="{}" v2="{}" v3="{}"/>"#,
+                triangle.verts[0], triangle.verts[1], triangle.verts[2]
+            )?;
+        }
+        writeln!(xml, "    </triangles>")?;
+        writeln!(xml, "   </mesh>")?;
+        writeln!(xml, "  </object>")?;
+    }
+
+    writeln!(xml, " </resources>")?;
+    writeln!(xml, " <build>")?;
+    for index in 0..models.len() {
+        writeln!(xml, r#"  <item objectid="{}"/>"#, index + 1)?;
+    }
+    writeln!(xml, " </build>")?;
+    writeln!(xml, "</model>")?;
+
+    Ok(xml)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{write_test_file, Loader, ThreeMFLoader};
+
+    #[test]
+    fn written_files_load_back() {
+        //Transforms are applied before writing so the placed vertices are saved
+        let transform = &Transform::new_translation_transform(100.5, 20.25, 0.0)
+            * &Transform::new_rotation_transform(0.0, 0.0, 0.3);
+        let vertices: Vec<Vertex> = [
+            (0.0, 0.0, 0.0),
+            (10.0, 0.0, 0.0),
+            (0.0, 10.0, 0.0),
+            (0.0, 0.0, 10.0),
+        ]
+        .iter()
+        .map(|(x, y, z)| {
+            &transform
+                * Vertex {
+                    x: *x,
+                    y: *y,
+                    z: *z,
+                }
+        })
+        .collect();
+        let triangles = vec![
+            IndexedTriangle { verts: [0, 2, 1] },
+            IndexedTriangle { verts: [0, 1, 3] },
+            IndexedTriangle { verts: [0, 3, 2] },
+            IndexedTriangle { verts: [1, 2, 3] },
+        ];
+        let models = vec![
+            (vertices.clone(), triangles.clone()),
+            (vertices.clone(), triangles.clone()),
+        ];
+
+        let infos = vec![
+            ObjectInfo {
+                name: Some("Tetra & <Co>".to_string()),
+                part_number: Some("A-1".to_string()),
+                metadata: [("Designer".to_string(), "Someone".to_string())]
+                    .into_iter()
+                    .collect(),
+                settings: Some(PartialSettings {
+                    infill_percentage: Some(0.5),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ObjectInfo::default(),
+        ];
+
+        let settings = Settings {
+            layer_height: 0.25,
+            number_of_perimeters: 5,
+            ..Default::default()
+        };
+
+        let filepath = write_test_file("written.3mf", &[]);
+        ThreeMFWriter {}
+            .write(&filepath, &models, &infos, &settings)
+            .expect("Temp file is writable");
+
+        let loaded = ThreeMFLoader {}
+            .load_with_info(&filepath)
+            .expect("Written file is valid");
+
+        assert_eq!(loaded.len(), 2);
+        for (info, loaded_vertices, loaded_triangles) in &loaded {
+            assert_eq!(loaded_vertices, &vertices);
+            assert_eq!(loaded_triangles, &triangles);
+            assert_eq!(info.instance, 0);
+        }
+        assert_eq!(loaded[0].0.name.as_deref(), Some("Tetra & <Co>"));
+        assert_eq!(loaded[0].0.part_number.as_deref(), Some("A-1"));
+        assert_eq!(loaded[0].0.metadata["Designer"], "Someone");
+
+        let project = ThreeMFLoader {}
+            .load_project_settings(&filepath)
+            .expect("Written file is valid");
+
+        let complete_settings = project.complete_settings.expect("Settings are written");
+        assert_eq!(
+            serde_json::to_string(&complete_settings).expect("Settings serialize"),
+            serde_json::to_string(&settings).expect("Settings serialize")
+        );
+        assert_eq!(project.objects.len(), 1);
+        assert_eq!(project.objects[&1].infill_percentage, Some(0.5));
+    }
+}
@@ -0,0 +1,348 @@
+use geo::{ConvexHull, Coord, MultiPoint, Point};
+use gladius_shared::error::SlicerErrors;
+use gladius_shared::settings::Settings;
+use gladius_shared::types::{IndexedTriangle, Transform, Vertex};
+
+///Minimum gap left between the brims of neighbouring objects in mm
+const OBJECT_SPACING: f64 = 2.0;
+
+///The footprint of a model after rotating it for packing
+struct Footprint {
+    index: usize,
+    angle: f64,
+    min_x: f64,
+    min_y: f64,
+    width: f64,
+    depth: f64,
+    min_z: f64,
+}
+
+/// Pack the models onto the build plate without overlapping.
+///
+/// Each model's footprint is the bounding rectangle of its first layer cross section grown by the brim
+/// width and spacing, so overhangs above the first layer may reach over neighbouring models.
+/// The bed edges are kept clear for the skirt. If rotation is allowed each model is rotated around z to
+/// minimise its footprint. Returns the transform for each model that places it on the plate at z = 0.
+pub fn arrange_models(
+    models: &[(&[Vertex], &[IndexedTriangle])],
+    settings: &Settings,
+    allow_rotation: bool,
+) -> Result<Vec<Transform>, SlicerErrors> {
+    let clearance = settings.brim_width.unwrap_or(0.0) + OBJECT_SPACING / 2.0;
+    let edge_margin = settings
+        .skirt
+        .as_ref()
+        .map(|skirt| skirt.distance + settings.extrusion_width.exterior_surface_perimeter)
+        .unwrap_or(0.0);
+
+    let usable_x = settings.print_x - 2.0 * edge_margin;
+    let usable_y = settings.print_y - 2.0 * edge_margin;
+
+    //The first layer is sliced halfway through its height
+    let first_layer_offset = settings.get_layer_settings(0, 0.0).layer_height / 2.0;
+
+    let mut footprints: Vec<Footprint> = models
+        .iter()
+        .enumerate()
+        .map(|(index, (vertices, triangles))| {
+            get_footprint(
+                index,
+                vertices,
+                triangles,
+                first_layer_offset,
+                allow_rotation,
+                clearance,
+            )
+        })
+        .collect();
+
+    //Shelf packing from the deepest footprint to the shallowest
+    footprints.sort_by(|a, b| {
+        b.depth
+            .partial_cmp(&a.depth)
+            .expect("No NAN footprints are allowed")
+    });
+
+    let mut positions = vec![(0.0, 0.0); footprints.len()];
+    let mut shelf_x = 0.0;
+    let mut shelf_y = 0.0;
+    let mut shelf_depth = 0.0;
+    let mut used_x: f64 = 0.0;
+
+    for (position, footprint) in positions.iter_mut().zip(&footprints) {
+        if shelf_x > 0.0 && shelf_x + footprint.width > usable_x {
+            shelf_y += shelf_depth;
+            shelf_x = 0.0;
+            shelf_depth = 0.0;
+        }
+
+        *position = (shelf_x, shelf_y);
+        shelf_x += footprint.width;
+        shelf_depth = f64::max(shelf_depth, footprint.depth);
+        used_x = used_x.max(shelf_x);
+    }
+
+    let used_y = shelf_y + shelf_depth;
+
+    if used_x > usable_x || used_y > usable_y {
+        return Err(SlicerErrors::ModelsDoNotFitBuildArea {
+            objects: models.len(),
+            required_x: used_x + 2.0 * edge_margin,
+            required_y: used_y + 2.0 * edge_margin,
+        });
+    }
+
+    //Centre the packed models on the plate
+    let offset_x = (settings.print_x - used_x) / 2.0;
+    let offset_y = (settings.print_y - used_y) / 2.0;
+
+    let mut transforms: Vec<(usize, Transform)> = positions
+        .into_iter()
+        .zip(footprints)
+        .map(|((x, y), footprint)| {
+            let translation = Transform::new_translation_transform(
+                offset_x + x - footprint.min_x,
+                offset_y + y - footprint.min_y,
+                -footprint.min_z,
+            );
+            let rotation = Transform::new_rotation_transform(0.0, 0.0, footprint.angle);
+
+            (footprint.index, &translation * &rotation)
+        })
+        .collect();
+
+    //Return the transforms in the same order as the models
+    transforms.sort_by_key(|(index, _)| *index);
+
+    Ok(transforms
+        .into_iter()
+        .map(|(_, transform)| transform)
+        .collect())
+}
+
+fn get_footprint(
+    index: usize,
+    vertices: &[Vertex],
+    triangles: &[IndexedTriangle],
+    first_layer_offset: f64,
+    allow_rotation: bool,
+    clearance: f64,
+) -> Footprint {
+    let min_z = vertices.iter().map(|v| v.z).fold(f64::INFINITY, f64::min);
+    let first_layer_points = first_layer_points(vertices, triangles, min_z + first_layer_offset);
+
+    //Models too thin to reach the first layer fall back to every vertex
+    let points = if first_layer_points.is_empty() {
+        vertices.iter().map(|v| Coord { x: v.x, y: v.y }).collect()
+    } else {
+        first_layer_points
+    };
+
+    let hull = MultiPoint(points.into_iter().map(Point).collect()).convex_hull();
+
+    let bounds = |angle: f64| {
+        let (sin, cos) = angle.sin_cos();
+        hull.exterior().0.iter().fold(
+            (
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(min_x, max_x, min_y, max_y), c| {
+                let x = c.x * cos - c.y * sin;
+                let y = c.x * sin + c.y * cos;
+                (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y))
+            },
+        )
+    };
+
+    let area = |angle: f64| {
+        let (min_x, max_x, min_y, max_y) = bounds(angle);
+        (max_x - min_x) * (max_y - min_y)
+    };
+
+    let mut angle = 0.0;
+
+    if allow_rotation {
+        //The smallest bounding rectangle has a side along an edge of the convex hull
+        for line in hull.exterior().lines() {
+            let candidate = -(line.end.y - line.start.y).atan2(line.end.x - line.start.x);
+            if area(candidate) < area(angle) - f64::EPSILON {
+                angle = candidate;
+            }
+        }
+
+        //Lay the footprint along the x axis so shelves are shallow
+        let (min_x, max_x, min_y, max_y) = bounds(angle);
+        if max_y - min_y > max_x - min_x {
+            angle += std::f64::consts::FRAC_PI_2;
+        }
+    }
+
+    let (min_x, max_x, min_y, max_y) = bounds(angle);
+
+    Footprint {
+        index,
+        angle,
+        min_x: min_x - clearance,
+        min_y: min_y - clearance,
+        width: max_x - min_x + 2.0 * clearance,
+        depth: max_y - min_y + 2.0 * clearance,
+        min_z,
+    }
+}
+
+///The points where the triangle edges cross the plane at the height, the hull of which is the hull of
+///the cross section
+fn first_layer_points(
+    vertices: &[Vertex],
+    triangles: &[IndexedTriangle],
+    height: f64,
+) -> Vec<Coord<f64>> {
+    let mut points = vec![];
+
+    for triangle in triangles {
+        for edge in 0..3 {
+            let start = &vertices[triangle.verts[edge]];
+            let end = &vertices[triangle.verts[(edge + 1) % 3]];
+
+            if start.z.min(end.z) > height || start.z.max(end.z) < height {
+                continue;
+            }
+
+            if start.z == end.z {
+                points.push(Coord {
+                    x: start.x,
+                    y: start.y,
+                });
+                points.push(Coord { x: end.x, y: end.y });
+            } else {
+                let t = (height - start.z) / (end.z - start.z);
+                points.push(Coord {
+                    x: start.x + (end.x - start.x) * t,
+                    y: start.y + (end.y - start.y) * t,
+                });
+            }
+        }
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repair::Model;
+    use crate::test_utils;
+
+    ///A cuboid away from the origin so arranging has to move it onto the bed
+    fn cuboid(x: f64, y: f64, z: f64) -> Model {
+        test_utils::cuboid((500.0, -300.0, 7.0), (x, y, z))
+    }
+
+    fn as_slices(models: &[Model]) -> Vec<(&[Vertex], &[IndexedTriangle])> {
+        models
+            .iter()
+            .map(|(v, t)| (v.as_slice(), t.as_slice()))
+            .collect()
+    }
+
+    fn placed_bounds(vertices: &[Vertex], transform: &Transform) -> (f64, f64, f64, f64, f64) {
+        vertices.iter().map(|v| transform * *v).fold(
+            (
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+            ),
+            |a, v| {
+                (
+                    a.0.min(v.x),
+                    a.1.max(v.x),
+                    a.2.min(v.y),
+                    a.3.max(v.y),
+                    a.4.min(v.z),
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn models_do_not_overlap() {
+        let settings = Settings::default();
+        let models = vec![cuboid(40.0, 30.0, 10.0); 6];
+
+        let transforms = arrange_models(&as_slices(&models), &settings, false).expect("Models fit");
+        let bounds: Vec<_> = models
+            .iter()
+            .zip(transforms.iter())
+            .map(|((m, _), t)| placed_bounds(m, t))
+            .collect();
+
+        for (i, a) in bounds.iter().enumerate() {
+            assert!(a.0 >= 0.0 && a.1 <= settings.print_x);
+            assert!(a.2 >= 0.0 && a.3 <= settings.print_y);
+            assert!(a.4.abs() < 0.000001);
+
+            for b in bounds.iter().skip(i + 1) {
+                let separated = a.1 + OBJECT_SPACING <= b.0 + 0.000001
+                    || b.1 + OBJECT_SPACING <= a.0 + 0.000001
+                    || a.3 + OBJECT_SPACING <= b.2 + 0.000001
+                    || b.3 + OBJECT_SPACING <= a.2 + 0.000001;
+                assert!(separated);
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_lays_models_along_x() {
+        let settings = Settings::default();
+        let models = vec![cuboid(10.0, 80.0, 10.0)];
+
+        let transforms = arrange_models(&as_slices(&models), &settings, true).expect("Model fits");
+        let (min_x, max_x, min_y, max_y, _) = placed_bounds(&models[0].0, &transforms[0]);
+
+        assert!((max_x - min_x - 80.0).abs() < 0.000001);
+        assert!((max_y - min_y - 10.0).abs() < 0.000001);
+    }
+
+    #[test]
+    fn too_many_models_fail() {
+        let settings = Settings::default();
+        let models = vec![cuboid(100.0, 100.0, 10.0); 12];
+
+        assert!(matches!(
+            arrange_models(&as_slices(&models), &settings, true),
+            Err(SlicerErrors::ModelsDoNotFitBuildArea { objects: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn footprint_is_the_first_layer() {
+        let settings = Settings::default();
+
+        //A narrow stem under a wide cap that overhangs it
+        let (mut vertices, mut triangles) = cuboid(10.0, 10.0, 10.0);
+        let (cap_vertices, cap_triangles) = cuboid(60.0, 60.0, 5.0);
+        let offset = vertices.len();
+        vertices.extend(cap_vertices.into_iter().map(|v| Vertex {
+            x: v.x - 25.0,
+            y: v.y - 25.0,
+            z: v.z + 10.0,
+        }));
+        triangles.extend(cap_triangles.into_iter().map(|t| IndexedTriangle {
+            verts: t.verts.map(|i| i + offset),
+        }));
+
+        let first_layer_offset = settings.get_layer_settings(0, 0.0).layer_height / 2.0;
+        let footprint = get_footprint(0, &vertices, &triangles, first_layer_offset, false, 1.0);
+
+        assert!((footprint.width - 12.0).abs() < 0.000001);
+        assert!((footprint.depth - 12.0).abs() < 0.000001);
+        assert!((footprint.min_x - 499.0).abs() < 0.000001);
+        assert!((footprint.min_y + 301.0).abs() < 0.000001);
+        assert!((footprint.min_z - 7.0).abs() < 0.000001);
+    }
+}
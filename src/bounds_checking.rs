@@ -34,11 +34,14 @@ pub fn check_moves_bounds(moves: &[Command], settings: &Settings) -> Result<(),
     moves
         .iter()
         .map(|command| match command {
-            Command::MoveTo { end, .. } | Command::MoveAndExtrude { end, .. } => {
+            Command::MoveTo { end, z }
+            | Command::MoveAndExtrude { end, z, .. }
+            | Command::Arc { end, z, .. } => {
                 if end.x < 0.0
                     || end.x > settings.print_x
                     || end.y < 0.0
                     || end.y > settings.print_y
+                    || z.is_some_and(|z| z > settings.print_z || z < 0.0)
                 {
                     Err(SlicerErrors::MovesOutsideBuildArea)
                 } else {
@@ -52,9 +55,6 @@ pub fn check_moves_bounds(moves: &[Command], settings: &Settings) -> Result<(),
                     Ok(())
                 }
             }
-            Command::Arc { .. } => {
-                unimplemented!()
-            }
             Command::SetState { .. }
             | Command::Delay { .. }
             | Command::NoAction
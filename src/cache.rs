@@ -0,0 +1,410 @@
+use crate::generate_perimeters;
+use crate::repair::Model;
+use crate::slicing::slice;
+use crate::tower::{create_towers, TriangleTower};
+use crate::utils::{display_state_update, CancelToken, MessageSink};
+use gladius_shared::error::SlicerErrors;
+use gladius_shared::settings::{
+    AdaptiveLayerHeightSettings, LayerRange, MovementParameter, PartialSettings, Settings,
+    SkirtSettings, SupportSettings,
+};
+use gladius_shared::types::{Object, ObjectInfo};
+use log::debug;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Keeps the results of the slow slicing stages so that changing a setting only reruns the
+/// stages that depend on it. Only the most recent result of each stage is kept.
+#[derive(Default)]
+pub struct SliceCache {
+    towers: Option<(u64, Vec<TriangleTower>)>,
+    slices: Option<((u64, String), Vec<Object>)>,
+    perimeters: Option<((u64, String, String), Vec<Object>)>,
+}
+
+impl SliceCache {
+    /// Get the objects with their slices, supports, skirt, brim and perimeters generated,
+    /// reusing the cached stages when the models and the settings they depend on are unchanged
+    pub fn get_perimeters(
+        &mut self,
+        models: &[Model],
+        infos: &[ObjectInfo],
+        settings: &Settings,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<Vec<Object>, SlicerErrors> {
+        let mesh_key = mesh_hash(models);
+        let slice_key = (mesh_key, slice_settings_key(infos, settings));
+        let perimeter_key = (
+            slice_key.0,
+            slice_key.1.clone(),
+            perimeter_settings_key(settings),
+        );
+
+        if self.perimeters.as_ref().map(|(key, _)| key) == Some(&perimeter_key) {
+            debug!("Using cached perimeters");
+        } else {
+            if self.slices.as_ref().map(|(key, _)| key) == Some(&slice_key) {
+                debug!("Using cached slices");
+            } else {
+                if self.towers.as_ref().map(|(key, _)| *key) == Some(mesh_key) {
+                    debug!("Using cached towers");
+                } else {
+                    display_state_update("Creating Towers", messages);
+                    self.towers = None;
+                    self.towers = Some((mesh_key, create_towers(models)?));
+                }
+
+                let towers = &self.towers.as_ref().expect("Towers set above").1;
+
+                display_state_update("Slicing", messages);
+                self.slices = None;
+                self.slices = Some((
+                    slice_key,
+                    slice(towers, infos.to_vec(), settings, cancel, messages)?,
+                ));
+            }
+
+            let mut objects = self.slices.as_ref().expect("Slices set above").1.clone();
+            refresh_settings(&mut objects, infos, settings)?;
+
+            display_state_update("Generating Moves", messages);
+            generate_perimeters(&mut objects, settings, cancel, messages)?;
+            self.perimeters = Some((perimeter_key, objects));
+        }
+
+        let mut objects = self
+            .perimeters
+            .as_ref()
+            .expect("Perimeters set above")
+            .1
+            .clone();
+        refresh_settings(&mut objects, infos, settings)?;
+
+        Ok(objects)
+    }
+}
+
+/// Update the cached objects to the current names and settings as the cache keys
+/// only cover the settings that change the geometry
+fn refresh_settings(
+    objects: &mut [Object],
+    infos: &[ObjectInfo],
+    settings: &Settings,
+) -> Result<(), SlicerErrors> {
+    for (object, info) in objects.iter_mut().zip(infos) {
+        object.info = info.clone();
+        object.settings = info
+            .settings
+            .clone()
+            .map(|overrides| settings.with_overrides(overrides))
+            .transpose()?;
+
+        let settings = object.settings.as_ref().unwrap_or(settings);
+
+        for (layer, slice) in object.layers.iter_mut().enumerate() {
+            let layer_height = slice.top_height - slice.bottom_height;
+            slice.layer_settings =
+                settings.get_layer_settings(layer, (slice.bottom_height + slice.top_height) / 2.0);
+            slice.layer_settings.layer_height = layer_height;
+        }
+    }
+
+    Ok(())
+}
+
+/// Hash of the geometry of all models
+fn mesh_hash(models: &[Model]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    models.len().hash(&mut hasher);
+    for (vertices, triangles) in models {
+        vertices.len().hash(&mut hasher);
+        for vertex in vertices {
+            vertex.x.to_bits().hash(&mut hasher);
+            vertex.y.to_bits().hash(&mut hasher);
+            vertex.z.to_bits().hash(&mut hasher);
+        }
+
+        triangles.len().hash(&mut hasher);
+        for triangle in triangles {
+            triangle.verts.hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+/// The settings that change where layers are sliced
+fn slice_settings_key(infos: &[ObjectInfo], settings: &Settings) -> String {
+    #[derive(Serialize)]
+    struct SliceSettings<'a> {
+        layer_height: f64,
+        adaptive_layer_height: &'a Option<AdaptiveLayerHeightSettings>,
+        layer_heights: Vec<(&'a LayerRange, f64)>,
+        overrides: Vec<&'a Option<PartialSettings>>,
+    }
+
+    serde_json::to_string(&SliceSettings {
+        layer_height: settings.layer_height,
+        adaptive_layer_height: &settings.adaptive_layer_height,
+        layer_heights: settings
+            .layer_settings
+            .iter()
+            .filter_map(|(range, layer)| layer.layer_height.map(|height| (range, height)))
+            .collect(),
+        overrides: infos.iter().map(|info| &info.settings).collect(),
+    })
+    .expect("Settings should always serialize")
+}
+
+/// The settings used by the object passes, shrinking and perimeters
+fn perimeter_settings_key(settings: &Settings) -> String {
+    ///The layer settings that change the perimeters, other layer settings are refreshed on reuse
+    #[derive(Serialize)]
+    struct LayerGeometry<'a> {
+        layer_height: Option<f64>,
+        extrusion_width: &'a Option<MovementParameter>,
+        layer_shrink_amount: Option<f64>,
+        inner_perimeters_first: Option<bool>,
+    }
+
+    #[derive(Serialize)]
+    struct PerimeterSettings<'a> {
+        extrusion_width: &'a MovementParameter,
+        skirt: &'a Option<SkirtSettings>,
+        support: &'a Option<SupportSettings>,
+        brim_width: Option<f64>,
+        layer_shrink_amount: Option<f64>,
+        inner_perimeters_first: bool,
+        number_of_perimeters: usize,
+        bottom_layers: usize,
+        spiral_vase: bool,
+        print_x: f64,
+        print_y: f64,
+        layer_settings: Vec<(&'a LayerRange, LayerGeometry<'a>)>,
+    }
+
+    serde_json::to_string(&PerimeterSettings {
+        extrusion_width: &settings.extrusion_width,
+        skirt: &settings.skirt,
+        support: &settings.support,
+        brim_width: settings.brim_width,
+        layer_shrink_amount: settings.layer_shrink_amount,
+        inner_perimeters_first: settings.inner_perimeters_first,
+        number_of_perimeters: settings.number_of_perimeters,
+        bottom_layers: settings.bottom_layers,
+        spiral_vase: settings.spiral_vase,
+        print_x: settings.print_x,
+        print_y: settings.print_y,
+        layer_settings: settings
+            .layer_settings
+            .iter()
+            .filter(|(_, layer)| {
+                layer.layer_height.is_some()
+                    || layer.extrusion_width.is_some()
+                    || layer.layer_shrink_amount.is_some()
+                    || layer.inner_perimeters_first.is_some()
+            })
+            .map(|(range, layer)| {
+                (
+                    range,
+                    LayerGeometry {
+                        layer_height: layer.layer_height,
+                        extrusion_width: &layer.extrusion_width,
+                        layer_shrink_amount: layer.layer_shrink_amount,
+                        inner_perimeters_first: layer.inner_perimeters_first,
+                    },
+                )
+            })
+            .collect(),
+    })
+    .expect("Settings should always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::cube;
+    use gladius_shared::settings::PartialLayerSettings;
+    use gladius_shared::types::{IndexedTriangle, Vertex};
+
+    fn model(offset: f64) -> Model {
+        (
+            vec![
+                Vertex {
+                    x: offset,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                Vertex {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                Vertex {
+                    x: 0.0,
+                    y: 1.0,
+                    z: 1.0,
+                },
+            ],
+            vec![IndexedTriangle { verts: [0, 1, 2] }],
+        )
+    }
+
+    #[test]
+    fn mesh_hash_follows_geometry() {
+        assert_eq!(mesh_hash(&[model(0.0)]), mesh_hash(&[model(0.0)]));
+        assert_ne!(mesh_hash(&[model(0.0)]), mesh_hash(&[model(0.5)]));
+        assert_ne!(
+            mesh_hash(&[model(0.0)]),
+            mesh_hash(&[model(0.0), model(0.0)])
+        );
+    }
+
+    #[test]
+    fn fill_settings_do_not_change_keys() {
+        let settings = Settings::default();
+        let mut changed = Settings {
+            infill_percentage: 0.5,
+            ..Default::default()
+        };
+        changed.speed.infill = 10.0;
+
+        assert_eq!(
+            slice_settings_key(&[], &settings),
+            slice_settings_key(&[], &changed)
+        );
+        assert_eq!(
+            perimeter_settings_key(&settings),
+            perimeter_settings_key(&changed)
+        );
+    }
+
+    #[test]
+    fn geometry_settings_change_keys() {
+        let settings = Settings::default();
+        let mut changed = Settings {
+            number_of_perimeters: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            slice_settings_key(&[], &settings),
+            slice_settings_key(&[], &changed)
+        );
+        assert_ne!(
+            perimeter_settings_key(&settings),
+            perimeter_settings_key(&changed)
+        );
+
+        changed.layer_height = 0.3;
+        assert_ne!(
+            slice_settings_key(&[], &settings),
+            slice_settings_key(&[], &changed)
+        );
+    }
+
+    #[test]
+    fn layer_fill_settings_do_not_change_keys() {
+        let settings = Settings::default();
+        let mut changed = Settings::default();
+        changed.layer_settings.push((
+            LayerRange::LayerCountRange { start: 2, end: 5 },
+            PartialLayerSettings {
+                infill_percentage: Some(0.8),
+                extruder_temp: Some(250.0),
+                ..Default::default()
+            },
+        ));
+
+        assert_eq!(
+            perimeter_settings_key(&settings),
+            perimeter_settings_key(&changed)
+        );
+
+        changed.layer_settings.push((
+            LayerRange::LayerCountRange { start: 2, end: 5 },
+            PartialLayerSettings {
+                layer_shrink_amount: Some(0.5),
+                ..Default::default()
+            },
+        ));
+
+        assert_ne!(
+            perimeter_settings_key(&settings),
+            perimeter_settings_key(&changed)
+        );
+    }
+
+    ///Empty the cached stages so any stage that is reused returns no objects
+    fn empty_cached_stages(cache: &mut SliceCache) {
+        if let Some((_, towers)) = cache.towers.as_mut() {
+            towers.clear();
+        }
+        if let Some((_, objects)) = cache.slices.as_mut() {
+            objects.clear();
+        }
+        if let Some((_, objects)) = cache.perimeters.as_mut() {
+            objects.clear();
+        }
+    }
+
+    #[test]
+    fn unchanged_stages_are_reused() {
+        let models = [cube((0.0, 0.0), 10.0)];
+        let infos = [ObjectInfo::default()];
+        let cancel = CancelToken::default();
+        let mut cache = SliceCache::default();
+
+        let settings = Settings::default();
+        let objects = cache
+            .get_perimeters(&models, &infos, &settings, &cancel, MessageSink::default())
+            .expect("Cube slices");
+        assert_eq!(objects.len(), 1);
+
+        //Changing the infill reuses the towers, slices and perimeters
+        empty_cached_stages(&mut cache);
+        let settings = Settings {
+            infill_percentage: 0.8,
+            ..Default::default()
+        };
+        let objects = cache
+            .get_perimeters(&models, &infos, &settings, &cancel, MessageSink::default())
+            .expect("Cube slices");
+        assert!(objects.is_empty());
+
+        //Changing the perimeters reuses the slices
+        let settings = Settings {
+            number_of_perimeters: 1,
+            ..Default::default()
+        };
+        let objects = cache
+            .get_perimeters(&models, &infos, &settings, &cancel, MessageSink::default())
+            .expect("Cube slices");
+        assert!(objects.is_empty());
+
+        //Changing the layer height reuses the towers, which are empty so nothing is sliced
+        let settings = Settings {
+            layer_height: 0.3,
+            ..Default::default()
+        };
+        let objects = cache
+            .get_perimeters(&models, &infos, &settings, &cancel, MessageSink::default())
+            .expect("Cube slices");
+        assert!(objects.is_empty());
+
+        //New geometry is sliced again
+        let objects = cache
+            .get_perimeters(
+                &[cube((0.0, 0.0), 10.0), cube((0.0, 0.0), 10.0)],
+                &[ObjectInfo::default(), ObjectInfo::default()],
+                &settings,
+                &cancel,
+                MessageSink::default(),
+            )
+            .expect("Cubes slice");
+        assert_eq!(objects.len(), 2);
+    }
+}
@@ -10,14 +10,17 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
 
     let mut current_speed = 0.0;
     let mut current_pos = Coord { x: 0.0, y: 0.0 };
+    let mut current_z = 0.0;
 
     for cmd in moves {
         match cmd {
-            Command::MoveTo { end } => {
+            Command::MoveTo { end, z } => {
                 let x_diff = end.x - current_pos.x;
                 let y_diff = end.y - current_pos.y;
-                let d = ((x_diff * x_diff) + (y_diff * y_diff)).sqrt();
+                let z_diff = z.map_or(0.0, |z| z - current_z);
+                let d = ((x_diff * x_diff) + (y_diff * y_diff) + (z_diff * z_diff)).sqrt();
                 current_pos = *end;
+                current_z = z.unwrap_or(current_z);
                 if current_speed != 0.0 {
                     values.total_time += d / current_speed;
                 }
@@ -25,14 +28,17 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
             Command::MoveAndExtrude {
                 start,
                 end,
+                z,
                 width,
                 thickness,
             } => {
                 let x_diff = end.x - start.x;
                 let y_diff = end.y - start.y;
                 let d = ((x_diff * x_diff) + (y_diff * y_diff)).sqrt();
+                let z_diff = z.map_or(0.0, |z| z - current_z);
                 current_pos = *end;
-                values.total_time += d / current_speed;
+                current_z = z.unwrap_or(current_z);
+                values.total_time += ((d * d) + (z_diff * z_diff)).sqrt() / current_speed;
 
                 values.plastic_volume += width * thickness * d;
             }
@@ -52,6 +58,7 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
                 start,
                 end,
                 center,
+                z,
                 width,
                 thickness,
                 ..
@@ -72,11 +79,20 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
                 //Once you have the central angle in radians, multiply it by the radius to get the arc length.
                 let extrusion_length = central * radius;
 
-                values.total_time += extrusion_length / current_speed;
+                //helical arcs also travel the change in height
+                let z_diff = z.map_or(0.0, |z| z - current_z);
+                current_pos = *end;
+                current_z = z.unwrap_or(current_z);
+                values.total_time += ((extrusion_length * extrusion_length) + (z_diff * z_diff))
+                    .sqrt()
+                    / current_speed;
 
                 values.plastic_volume += width * thickness * extrusion_length;
             }
-            Command::NoAction | Command::LayerChange { .. } | Command::ChangeObject { .. } => {}
+            Command::LayerChange { z, .. } => {
+                current_z = *z;
+            }
+            Command::NoAction | Command::ChangeObject { .. } => {}
         }
     }
 
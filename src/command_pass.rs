@@ -52,7 +52,7 @@ impl CommandPass for SlowDownLayerPass {
                             start_index = start_index.or(Some(index));
                             end_index = index;
                             match cmd {
-                                Command::MoveTo { end } => {
+                                Command::MoveTo { end, .. } => {
                                     let x_diff = end.x - current_pos.x;
                                     let y_diff = end.y - current_pos.y;
                                     let d = ((x_diff * x_diff) + (y_diff * y_diff)).sqrt();
@@ -61,12 +61,7 @@ impl CommandPass for SlowDownLayerPass {
                                         non_move_time += d / current_speed;
                                     }
                                 }
-                                Command::MoveAndExtrude {
-                                    start,
-                                    end,
-                                    width: _width,
-                                    thickness: _thickness,
-                                } => {
+                                Command::MoveAndExtrude { start, end, .. } => {
                                     let x_diff = end.x - start.x;
                                     let y_diff = end.y - start.y;
                                     let d = ((x_diff * x_diff) + (y_diff * y_diff)).sqrt();
@@ -1,11 +1,61 @@
+use crate::utils::{CancelToken, MessageSink, ProgressCounter};
 use crate::{Command, Settings};
+use gladius_shared::error::SlicerErrors;
+use gladius_shared::messages::ProgressStage;
 use gladius_shared::types::RetractionType;
+use std::fs::File;
 use std::io::{BufWriter, Write};
 
+///Write the G-code to a new file, the partial file is removed if writing fails or is cancelled
+pub fn convert_to_file(
+    cmds: &[Command],
+    settings: &Settings,
+    file_path: &str,
+    cancel: &CancelToken,
+    messages: MessageSink,
+) -> Result<(), SlicerErrors> {
+    let mut file = File::create(file_path).map_err(|_| SlicerErrors::FileCreateError {
+        filepath: file_path.to_string(),
+    })?;
+
+    let result = convert(cmds, settings, &mut file, cancel, messages);
+    drop(file);
+
+    result.map_err(|err| {
+        std::fs::remove_file(file_path).ok();
+
+        match err.downcast::<SlicerErrors>() {
+            Ok(slicer_error) => *slicer_error,
+            Err(_) => SlicerErrors::FileWriteError {
+                filepath: file_path.to_string(),
+            },
+        }
+    })
+}
+
+///Convert the commands to G-code in memory
+pub fn convert_to_string(
+    cmds: &[Command],
+    settings: &Settings,
+    cancel: &CancelToken,
+    messages: MessageSink,
+) -> Result<String, SlicerErrors> {
+    let mut gcode: Vec<u8> = Vec::new();
+
+    convert(cmds, settings, &mut gcode, cancel, messages).map_err(|err| {
+        *err.downcast::<SlicerErrors>()
+            .expect("Writing to Vec shouldn't fail")
+    })?;
+
+    Ok(String::from_utf8(gcode).expect("All write occur from write macro so should be utf8"))
+}
+
 pub fn convert(
     cmds: &[Command],
     settings: &Settings,
     write: &mut impl Write,
+    cancel: &CancelToken,
+    messages: MessageSink,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut current_z = 0.0;
     let mut layer_count = 0;
@@ -17,7 +67,7 @@ pub fn convert(
         current_z,
         layer_count,
         None,
-        current_object,
+        current_object.as_ref(),
         settings,
     );
 
@@ -53,12 +103,25 @@ pub fn convert(
     writeln!(write_buf, "G90 ; use absolute Coords")?;
     writeln!(write_buf, "M83 ; use relative distances for extrusion")?;
 
+    let progress = ProgressCounter::new(ProgressStage::Converting, None, cmds.len(), messages);
+
     for cmd in cmds {
+        cancel.check()?;
+        progress.increment();
+
         match cmd {
-            Command::MoveTo { end, .. } => writeln!(write_buf, "G1 X{:.5} Y{:.5}", end.x, end.y)?,
+            Command::MoveTo { end, z } => {
+                if let Some(z) = z {
+                    current_z = *z;
+                    writeln!(write_buf, "G1 X{:.5} Y{:.5} Z{:.5}", end.x, end.y, z)?;
+                } else {
+                    writeln!(write_buf, "G1 X{:.5} Y{:.5}", end.x, end.y)?;
+                }
+            }
             Command::MoveAndExtrude {
                 start,
                 end,
+                z,
                 width,
                 thickness,
             } => {
@@ -79,7 +142,16 @@ pub fn convert(
                     / 4.0;
                 let extrude = extrusion_volume / filament_area;
 
-                writeln!(write_buf, "G1 X{:.5} Y{:.5} E{:.5}", end.x, end.y, extrude)?;
+                if let Some(z) = z {
+                    current_z = *z;
+                    writeln!(
+                        write_buf,
+                        "G1 X{:.5} Y{:.5} Z{:.5} E{:.5}",
+                        end.x, end.y, z, extrude
+                    )?;
+                } else {
+                    writeln!(write_buf, "G1 X{:.5} Y{:.5} E{:.5}", end.x, end.y, extrude)?;
+                }
             }
             Command::SetState { new_state } => {
                 match &new_state.retract {
@@ -179,7 +251,7 @@ pub fn convert(
                         current_z,
                         layer_count,
                         None,
-                        current_object,
+                        current_object.as_ref(),
                         settings
                     )
                 )?;
@@ -195,7 +267,7 @@ pub fn convert(
                         current_z,
                         layer_count,
                         None,
-                        current_object,
+                        current_object.as_ref(),
                         settings
                     )
                 )?;
@@ -207,6 +279,7 @@ pub fn convert(
                 start,
                 end,
                 center,
+                z,
                 clockwise,
                 width,
                 thickness,
@@ -232,19 +305,37 @@ pub fn convert(
                     / (std::f64::consts::PI
                         * settings.filament.diameter
                         * settings.filament.diameter);
-                writeln!(
-                    write_buf,
-                    "{} X{:.5} Y{:.5} I{:.5} J{:.5} E{:.5}",
-                    if *clockwise { "G2" } else { "G3" },
-                    end.x,
-                    end.y,
-                    center.x - start.x,
-                    center.y - start.y,
-                    extrude
-                )?;
+                let arc_command = if *clockwise { "G2" } else { "G3" };
+                if let Some(z) = z {
+                    //a z move during an arc makes it helical
+                    current_z = *z;
+                    writeln!(
+                        write_buf,
+                        "{} X{:.5} Y{:.5} Z{:.5} I{:.5} J{:.5} E{:.5}",
+                        arc_command,
+                        end.x,
+                        end.y,
+                        z,
+                        center.x - start.x,
+                        center.y - start.y,
+                        extrude
+                    )?;
+                } else {
+                    writeln!(
+                        write_buf,
+                        "{} X{:.5} Y{:.5} I{:.5} J{:.5} E{:.5}",
+                        arc_command,
+                        end.x,
+                        end.y,
+                        center.x - start.x,
+                        center.y - start.y,
+                        extrude
+                    )?;
+                }
             }
-            Command::ChangeObject { object } => {
-                let previous_object = std::mem::replace(&mut current_object, Some(*object));
+            Command::ChangeObject { object, name } => {
+                let previous_object =
+                    std::mem::replace(&mut current_object, Some((*object, name.clone())));
                 writeln!(
                     write_buf,
                     "{}",
@@ -252,8 +343,8 @@ pub fn convert(
                         settings.object_change_instructions.clone(),
                         current_z,
                         layer_count,
-                        previous_object,
-                        current_object,
+                        previous_object.as_ref(),
+                        current_object.as_ref(),
                         settings
                     )
                 )?;
@@ -269,7 +360,7 @@ pub fn convert(
         current_z,
         layer_count,
         None,
-        current_object,
+        current_object.as_ref(),
         settings,
     );
 
@@ -286,8 +377,8 @@ fn convert_instructions(
     mut instructions: String,
     current_z_height: f64,
     layer_count: usize,
-    previous_object: Option<usize>,
-    current_object: Option<usize>,
+    previous_object: Option<&(usize, Option<String>)>,
+    current_object: Option<&(usize, Option<String>)>,
     settings: &Settings,
 ) -> String {
     let layer_settings = settings.get_layer_settings(layer_count, current_z_height);
@@ -306,17 +397,31 @@ fn convert_instructions(
 
     instructions = instructions.replace("[Layer Count]", &format!("{:.1}", layer_count));
 
+    instructions = instructions.replace(
+        "[Previous Object Name]",
+        previous_object
+            .and_then(|(_, name)| name.as_deref())
+            .unwrap_or_default(),
+    );
+
+    instructions = instructions.replace(
+        "[Current Object Name]",
+        current_object
+            .and_then(|(_, name)| name.as_deref())
+            .unwrap_or_default(),
+    );
+
     instructions = instructions.replace(
         "[Previous Object]",
         &previous_object
-            .map(|obj| obj.to_string())
+            .map(|(obj, _)| obj.to_string())
             .unwrap_or_default(),
     );
 
     instructions = instructions.replace(
         "[Current Object]",
         &current_object
-            .map(|obj| obj.to_string())
+            .map(|(obj, _)| obj.to_string())
             .unwrap_or_default(),
     );
 
@@ -1,32 +1,95 @@
-use crate::utils::show_error_message;
+use crate::arrange::arrange_models;
+use crate::orient::{find_orientation, rotation_to_bed};
+use crate::repair::Model;
+use crate::utils::{display_state_update, MessageSink};
 use crate::*;
-use std::path::PathBuf;
-use std::str::FromStr;
-
-pub fn files_input(
-    settings_path: Option<&str>,
-    input: Option<Vec<String>>,
-) -> Result<(Vec<(Vec<Vertex>, Vec<IndexedTriangle>)>, Settings), SlicerErrors> {
-    let settings: Settings = {
-        if let Some(str) = settings_path {
-            load_settings(str)
-        } else {
-            Ok(Settings::default())
+use gladius_shared::warning::SlicerWarnings;
+use nalgebra::Vector3;
+
+///The overhang angle used to orient models when support is disabled
+const DEFAULT_MAX_OVERHANG_ANGLE: f64 = 45.0;
+
+///The placed models, their identities, the settings to use and any warnings found while loading
+pub type LoadedInput = (Vec<Model>, Vec<ObjectInfo>, Settings, Vec<SlicerWarnings>);
+
+///Load and place the models of the inputs, project settings are applied to the returned settings
+pub fn load_models(
+    mut settings: Settings,
+    input: &[String],
+    use_project_settings: bool,
+    arrange: bool,
+    allow_rotation: bool,
+    messages: MessageSink,
+) -> Result<LoadedInput, SlicerErrors> {
+    let mut warnings = vec![];
+    let mut object_settings: HashMap<String, HashMap<usize, PartialSettings>> = HashMap::new();
+
+    if use_project_settings {
+        info!("Loading Project Settings");
+
+        let mut project_settings = ProjectSettings::default();
+        let mut projects = vec![];
+
+        for value in input {
+            let object: InputObject =
+                deser_hjson::from_str(value).map_err(|_| SlicerErrors::InputMisformat)?;
+            let model_path = object.get_model_path();
+
+            if model_path.to_lowercase().ends_with(".3mf") {
+                let mut project = ThreeMFLoader {}.load_project_settings(model_path)?;
+
+                //Earlier inputs take precedence
+                project_settings.complete_settings = project_settings
+                    .complete_settings
+                    .or_else(|| project.complete_settings.take());
+                project_settings.settings =
+                    project_settings.settings.combine(project.settings.clone());
+                project_settings.temperatures =
+                    project_settings.temperatures.combine(project.temperatures);
+                warnings.append(&mut project.warnings);
+                projects.push((model_path.to_string(), project));
+            }
+        }
+
+        //Complete settings saved by this slicer replace the settings file
+        if let Some(complete_settings) = project_settings.complete_settings.take() {
+            settings = complete_settings;
+        }
+
+        settings = project_settings.apply_to(&settings)?;
+
+        for (model_path, project) in projects {
+            object_settings.insert(model_path, project.object_overrides(&settings));
         }
-    }?;
+    }
 
     info!("Loading Input");
 
-    let converted_inputs: Vec<(Vec<Vertex>, Vec<IndexedTriangle>)> = input
-        .ok_or(SlicerErrors::NoInputProvided)?
-        .iter()
-        .try_fold(vec![], |mut vec, value| {
-            let object: InputObject =
+    //Indices of the models to place once every model is loaded
+    let mut arranged = vec![];
+
+    let mut converted_inputs: Vec<(ObjectInfo, Vec<Vertex>, Vec<IndexedTriangle>)> =
+        input.iter().try_fold(vec![], |mut vec, value| {
+            let mut object: InputObject =
                 deser_hjson::from_str(value).map_err(|_| SlicerErrors::InputMisformat)?;
+
+            if arrange {
+                if let InputObject::Auto(path) | InputObject::AutoTranslate(path, _, _) = object {
+                    object = InputObject::Arrange(path);
+                }
+            }
             let model_path = Path::new(object.get_model_path());
 
             debug!("Using input file: {:?}", model_path);
 
+            //Models without a name are named after their file
+            let file_name = model_path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .map(|str| str.to_string());
+
+            let project_objects = object_settings.get(object.get_model_path()).cloned();
+
             let extension = model_path.extension().and_then(OsStr::to_str).ok_or(
                 SlicerErrors::FileFormatNotSupported {
                     filepath: model_path.to_string_lossy().to_string(),
@@ -37,30 +100,68 @@ pub fn files_input(
             {
                 "stl" => Ok(&STLLoader {}),
                 "3mf" => Ok(&ThreeMFLoader {}),
+                "obj" => Ok(&OBJLoader {}),
+                "amf" => Ok(&AMFLoader {}),
+                "ply" => Ok(&PLYLoader {}),
                 _ => Err(SlicerErrors::FileFormatNotSupported {
                     filepath: model_path.to_string_lossy().to_string(),
                 }),
             };
 
-            let models = match loader?.load(model_path.to_str().ok_or(SlicerErrors::InputNotUTF8)?)
-            {
-                Ok(v) => v,
-                Err(err) => {
-                    show_error_message(err);
-                    std::process::exit(-1);
-                }
-            };
+            let models =
+                loader?.load_with_info(model_path.to_str().ok_or(SlicerErrors::InputNotUTF8)?)?;
 
             let (x, y) = match object {
                 InputObject::AutoTranslate(_, x, y) => (x, y),
                 _ => (0.0, 0.0),
             };
 
+            let meshes: Vec<(&[Vertex], &[IndexedTriangle])> = models
+                .iter()
+                .map(|(_info, v, t)| (v.as_slice(), t.as_slice()))
+                .collect();
+
+            //Rotation and scaling applied before the model is centered
+            let placement = match &object {
+                InputObject::AutoOrient(_, goal) => {
+                    let max_overhang_angle = settings
+                        .support
+                        .as_ref()
+                        .map(|support| support.max_overhang_angle)
+                        .unwrap_or(DEFAULT_MAX_OVERHANG_ANGLE);
+
+                    let (x, y, z) = find_orientation(&meshes, *goal, max_overhang_angle);
+
+                    display_state_update(
+                        &format!(
+                            "Oriented {} by rotating x {:.1}° y {:.1}° z {:.1}°",
+                            model_path.display(),
+                            x.to_degrees(),
+                            y.to_degrees(),
+                            z.to_degrees()
+                        ),
+                        messages,
+                    );
+
+                    Transform::new_rotation_transform(x, y, z)
+                }
+                InputObject::Transformed(path, transforms) => {
+                    compose_transforms(path, transforms, &meshes)?
+                }
+                _ => Transform::new_translation_transform(0.0, 0.0, 0.0),
+            };
+
             let transform = match object {
                 InputObject::Raw(_, transform) => transform,
-                InputObject::Auto(_) | InputObject::AutoTranslate(_, _, _) => {
-                    let (min_x, max_x, min_y, max_y, min_z) =
-                        models.iter().flat_map(|(v, _t)| v.iter()).fold(
+                InputObject::Auto(_)
+                | InputObject::AutoTranslate(_, _, _)
+                | InputObject::AutoOrient(_, _)
+                | InputObject::Transformed(_, _) => {
+                    let (min_x, max_x, min_y, max_y, min_z) = models
+                        .iter()
+                        .flat_map(|(_info, v, _t)| v.iter())
+                        .map(|v| &placement * *v)
+                        .fold(
                             (
                                 f64::INFINITY,
                                 f64::NEG_INFINITY,
@@ -78,11 +179,17 @@ pub fn files_input(
                                 )
                             },
                         );
-                    Transform::new_translation_transform(
+                    let translation = Transform::new_translation_transform(
                         (x + settings.print_x - (max_x + min_x)) / 2.,
                         (y + settings.print_y - (max_y + min_y)) / 2.,
                         -min_z,
-                    )
+                    );
+
+                    &translation * &placement
+                }
+                InputObject::Arrange(_) => {
+                    arranged.extend(vec.len()..vec.len() + models.len());
+                    Transform::new_translation_transform(0.0, 0.0, 0.0)
                 }
             };
 
@@ -91,20 +198,136 @@ pub fn files_input(
 
             debug!("Using Transform {}", trans_str);
 
-            vec.extend(models.into_iter().map(move |(mut v, t)| {
-                for vert in v.iter_mut() {
-                    *vert = &transform * *vert;
+            vec.extend(models.into_iter().map(move |(mut info, mut v, t)| {
+                let t = apply_transform(&transform, &mut v, t);
+
+                if info.name.is_none() {
+                    info.name = file_name.clone();
                 }
 
-                (v, t)
+                if let (Some(objects), Some(id)) = (&project_objects, info.source_id) {
+                    info.settings = objects.get(&id).cloned();
+                }
+
+                (info, v, t)
             }));
 
             Ok(vec)
         })?;
-    Ok((converted_inputs, settings))
+    if !arranged.is_empty() {
+        info!("Arranging {} Models", arranged.len());
+
+        let meshes: Vec<(&[Vertex], &[IndexedTriangle])> = arranged
+            .iter()
+            .map(|index| {
+                let (_, v, t) = &converted_inputs[*index];
+                (v.as_slice(), t.as_slice())
+            })
+            .collect();
+        let transforms = arrange_models(&meshes, &settings, allow_rotation)?;
+
+        for (index, transform) in arranged.into_iter().zip(transforms) {
+            let (_, v, t) = &mut converted_inputs[index];
+            *t = apply_transform(&transform, v, std::mem::take(t));
+        }
+    }
+
+    let (infos, models) = converted_inputs
+        .into_iter()
+        .map(|(info, v, t)| (info, (v, t)))
+        .unzip();
+
+    Ok((models, infos, settings, warnings))
+}
+
+///Combine the input transforms in the order they are applied into a single transform
+fn compose_transforms(
+    filepath: &str,
+    transforms: &[InputTransform],
+    meshes: &[(&[Vertex], &[IndexedTriangle])],
+) -> Result<Transform, SlicerErrors> {
+    transforms.iter().try_fold(
+        Transform::new_translation_transform(0.0, 0.0, 0.0),
+        |composed, input_transform| {
+            let transform = match *input_transform {
+                InputTransform::Scale(x, y, z) => Transform::new_scale_transform(x, y, z),
+                InputTransform::UniformScale(scale) => {
+                    Transform::new_scale_transform(scale, scale, scale)
+                }
+                InputTransform::Rotate(x, y, z) => Transform::new_rotation_transform(
+                    x.to_radians(),
+                    y.to_radians(),
+                    z.to_radians(),
+                ),
+                InputTransform::MirrorX => Transform::new_scale_transform(-1.0, 1.0, 1.0),
+                InputTransform::MirrorY => Transform::new_scale_transform(1.0, -1.0, 1.0),
+                InputTransform::MirrorZ => Transform::new_scale_transform(1.0, 1.0, -1.0),
+                InputTransform::LayFlat(face) => {
+                    let (vertices, triangle) = meshes
+                        .iter()
+                        .flat_map(|(v, t)| t.iter().map(move |triangle| (v, triangle)))
+                        .nth(face)
+                        .ok_or_else(|| SlicerErrors::LayFlatFaceNotFound {
+                            filepath: filepath.to_string(),
+                            face,
+                            faces: meshes.iter().map(|(_v, t)| t.len()).sum(),
+                        })?;
+
+                    //The face normal after the earlier transforms
+                    let [a, b, c] = triangle.verts.map(|index| {
+                        let v = &composed * vertices[index];
+                        Vector3::new(v.x, v.y, v.z)
+                    });
+                    let mut normal = (b - a).cross(&(c - a));
+
+                    //Faces with no area have no direction to place down
+                    if normal.norm() <= f64::EPSILON * (b - a).norm() * (c - a).norm() {
+                        return Err(SlicerErrors::LayFlatFaceDegenerate {
+                            filepath: filepath.to_string(),
+                            face,
+                        });
+                    }
+
+                    if composed.is_mirroring() {
+                        normal = -normal;
+                    }
+
+                    let (x, y, z) = rotation_to_bed(&normal);
+                    Transform::new_rotation_transform(x, y, z)
+                }
+            };
+
+            Ok(&transform * &composed)
+        },
+    )
+}
+
+///Apply the transform to the vertices and return the triangles with the lowest vertex first
+fn apply_transform(
+    transform: &Transform,
+    vertices: &mut [Vertex],
+    triangles: Vec<IndexedTriangle>,
+) -> Vec<IndexedTriangle> {
+    for vert in vertices.iter_mut() {
+        *vert = transform * *vert;
+    }
+
+    //Mirroring turns the model inside out unless the winding is reversed
+    let mirroring = transform.is_mirroring();
+
+    //The transform can change which vertex is lowest
+    triangles
+        .into_iter()
+        .map(|mut triangle| {
+            if mirroring {
+                triangle.verts.swap(1, 2);
+            }
+            lowest_vertex_first(triangle, vertices)
+        })
+        .collect()
 }
 
-fn load_settings(filepath: &str) -> Result<Settings, SlicerErrors> {
+pub fn load_settings(filepath: &str) -> Result<Settings, SlicerErrors> {
     let settings_data =
         std::fs::read_to_string(filepath).map_err(|_| SlicerErrors::SettingsFileNotFound {
             filepath: filepath.to_string(),
@@ -113,19 +336,251 @@ fn load_settings(filepath: &str) -> Result<Settings, SlicerErrors> {
         deser_hjson::from_str(&settings_data).map_err(|_| SlicerErrors::SettingsFileMisformat {
             filepath: filepath.to_string(),
         })?;
-    let current_path = std::env::current_dir().map_err(|_| SlicerErrors::SettingsFilePermission)?;
-    let mut path = PathBuf::from_str(filepath).map_err(|_| SlicerErrors::SettingsFileNotFound {
-        filepath: filepath.to_string(),
-    })?;
 
-    path.pop();
+    //Other files are relative to the directory of the settings file
+    let directory = Path::new(filepath)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    partial_settings.get_settings_relative_to(directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gladius_shared::writer::ThreeMFWriter;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("gladius_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn tetrahedron(x: f64, y: f64, z: f64) -> Model {
+        let vertices = [
+            (0.0, 0.0, 0.0),
+            (10.0, 0.0, 0.0),
+            (0.0, 10.0, 0.0),
+            (0.0, 0.0, 10.0),
+        ]
+        .iter()
+        .map(|(vx, vy, vz)| Vertex {
+            x: vx + x,
+            y: vy + y,
+            z: vz + z,
+        })
+        .collect();
+        let triangles = vec![
+            IndexedTriangle { verts: [0, 2, 1] },
+            IndexedTriangle { verts: [0, 1, 3] },
+            IndexedTriangle { verts: [0, 3, 2] },
+            IndexedTriangle { verts: [1, 2, 3] },
+        ];
+
+        (vertices, triangles)
+    }
+
+    #[test]
+    fn exported_plates_reload_in_place_as_raw_inputs() {
+        let settings = Settings {
+            layer_height: 0.3,
+            ..Default::default()
+        };
+        let model = tetrahedron(30.0, 40.0, 5.0);
+        let filepath = temp_path("plate.3mf");
+
+        ThreeMFWriter {}
+            .write(
+                &filepath,
+                std::slice::from_ref(&model),
+                &[ObjectInfo::default()],
+                &settings,
+            )
+            .expect("Temp file is writable");
 
-    std::env::set_current_dir(&path).expect("Path checked before");
+        let raw = format!(
+            "{{\"Raw\":[{:?},[[1.0,0.0,0.0,0.0],[0.0,1.0,0.0,0.0],[0.0,0.0,1.0,0.0],[0.0,0.0,0.0,1.0]]]}}",
+            filepath
+        );
+        let (models, _, loaded_settings, _) = load_models(
+            Settings::default(),
+            &[raw],
+            true,
+            false,
+            false,
+            MessageSink::default(),
+        )
+        .expect("Exported plate loads");
 
-    let settings = partial_settings.get_settings()?;
+        assert_eq!(models, vec![model.clone()]);
+        assert_eq!(loaded_settings.layer_height, 0.3);
+
+        //Automatic placement centers and drops the model again
+        let auto = format!("{{\"Auto\":{:?}}}", filepath);
+        let (models, _, _, _) = load_models(
+            Settings::default(),
+            &[auto],
+            true,
+            false,
+            false,
+            MessageSink::default(),
+        )
+        .expect("Exported plate loads");
+
+        assert_ne!(models, vec![model]);
+    }
+
+    fn face_normal(vertices: &[Vertex], triangle: &IndexedTriangle) -> Vector3<f64> {
+        let [a, b, c] = triangle.verts.map(|index| {
+            let v = vertices[index];
+            Vector3::new(v.x, v.y, v.z)
+        });
+        (b - a).cross(&(c - a))
+    }
+
+    #[test]
+    fn transforms_are_applied_in_order() {
+        let (vertices, triangles) = tetrahedron(0.0, 0.0, 0.0);
+        let meshes = [(vertices.as_slice(), triangles.as_slice())];
+        let point = Vertex {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+
+        let scale_then_rotate = compose_transforms(
+            "model.stl",
+            &[
+                InputTransform::Scale(2.0, 1.0, 1.0),
+                InputTransform::Rotate(0.0, 0.0, 90.0),
+            ],
+            &meshes,
+        )
+        .expect("Valid transforms");
+        let rotate_then_scale = compose_transforms(
+            "model.stl",
+            &[
+                InputTransform::Rotate(0.0, 0.0, 90.0),
+                InputTransform::Scale(2.0, 1.0, 1.0),
+            ],
+            &meshes,
+        )
+        .expect("Valid transforms");
+
+        let a = &scale_then_rotate * point;
+        let b = &rotate_then_scale * point;
+        assert!(a.x.abs() < 0.000001 && (a.y - 2.0).abs() < 0.000001);
+        assert!(b.x.abs() < 0.000001 && (b.y - 1.0).abs() < 0.000001);
+    }
+
+    #[test]
+    fn mirroring_keeps_faces_pointing_out() {
+        let (vertices, triangles) = tetrahedron(0.0, 0.0, 0.0);
+        let meshes = [(vertices.as_slice(), triangles.as_slice())];
+
+        for mirror in [
+            InputTransform::MirrorX,
+            InputTransform::MirrorY,
+            InputTransform::MirrorZ,
+        ] {
+            let transform =
+                compose_transforms("model.stl", &[mirror], &meshes).expect("Valid transforms");
+            let mut mirrored = vertices.clone();
+            let mirrored_triangles = apply_transform(&transform, &mut mirrored, triangles.clone());
+
+            let center = mirrored.iter().fold(Vector3::zeros(), |sum, v| {
+                sum + Vector3::new(v.x, v.y, v.z) / mirrored.len() as f64
+            });
+
+            for triangle in &mirrored_triangles {
+                let v = mirrored[triangle.verts[0]];
+                let outward = Vector3::new(v.x, v.y, v.z) - center;
+                assert!(face_normal(&mirrored, triangle).dot(&outward) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn lay_flat_places_the_face_down() {
+        let (vertices, triangles) = tetrahedron(0.0, 0.0, 0.0);
+        let meshes = [(vertices.as_slice(), triangles.as_slice())];
+
+        //The slanted face, also after mirroring reverses its winding
+        for transforms in [
+            vec![InputTransform::LayFlat(3)],
+            vec![InputTransform::MirrorX, InputTransform::LayFlat(3)],
+        ] {
+            let transform =
+                compose_transforms("model.stl", &transforms, &meshes).expect("Valid transforms");
+            let mut placed = vertices.clone();
+            let placed_triangles = apply_transform(&transform, &mut placed, triangles.clone());
+
+            let normal = face_normal(&placed, &placed_triangles[3]).normalize();
+            assert!((normal.z + 1.0).abs() < 0.000001);
+
+            let face_z = placed[placed_triangles[3].verts[0]].z;
+            assert!(placed.iter().all(|v| v.z >= face_z - 0.000001));
+        }
+    }
+
+    #[test]
+    fn lay_flat_on_a_face_without_area_fails() {
+        let (mut vertices, mut triangles) = tetrahedron(0.0, 0.0, 0.0);
+        vertices.push(Vertex {
+            x: 5.0,
+            y: 0.0,
+            z: 0.0,
+        });
+        triangles.push(IndexedTriangle { verts: [0, 4, 1] });
+        let meshes = [(vertices.as_slice(), triangles.as_slice())];
+
+        assert!(matches!(
+            compose_transforms("model.stl", &[InputTransform::LayFlat(4)], &meshes),
+            Err(SlicerErrors::LayFlatFaceDegenerate { face: 4, .. })
+        ));
+        assert!(matches!(
+            compose_transforms("model.stl", &[InputTransform::LayFlat(5)], &meshes),
+            Err(SlicerErrors::LayFlatFaceNotFound {
+                face: 5,
+                faces: 5,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn other_settings_files_are_relative_to_the_file_listing_them() {
+        let directory = std::path::PathBuf::from(temp_path("settings"));
+        std::fs::create_dir_all(directory.join("printer")).expect("Temp dir is writable");
+
+        let mut base = PartialSettings::from(&Settings::default());
+        base.other_files = Some(vec!["extra.json".to_string()]);
+
+        let files = [
+            (
+                "main.json",
+                "{\"other_files\": [\"printer/base.json\"], \"layer_height\": 0.25}".to_string(),
+            ),
+            (
+                "printer/base.json",
+                serde_json::to_string(&base).expect("Settings serialize"),
+            ),
+            ("printer/extra.json", "{\"brim_width\": 3.0}".to_string()),
+        ];
+        for (name, contents) in files.iter() {
+            std::fs::write(directory.join(name), contents).expect("Temp dir is writable");
+        }
 
-    //reset path
-    std::env::set_current_dir(current_path).expect("Path checked before");
+        let current_dir = std::env::current_dir().expect("Current dir exists");
+        let settings =
+            load_settings(&directory.join("main.json").to_string_lossy()).expect("Settings load");
 
-    Ok(settings)
+        assert_eq!(settings.layer_height, 0.25);
+        assert_eq!(settings.brim_width, Some(3.0));
+        assert_eq!(
+            std::env::current_dir().expect("Current dir exists"),
+            current_dir
+        );
+    }
 }
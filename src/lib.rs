@@ -0,0 +1,161 @@
+#![deny(clippy::unwrap_used)]
+
+use gladius_shared::loader::*;
+use gladius_shared::types::*;
+
+use crate::plotter::convert_objects_into_moves;
+use crate::tower::*;
+use geo::*;
+use gladius_shared::settings::{PartialSettings, Settings};
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::bounds_checking::check_moves_bounds;
+use crate::cache::SliceCache;
+use crate::command_pass::{CommandPass, OptimizePass, SlowDownLayerPass};
+use crate::plotter::polygon_operations::PolygonOperations;
+use crate::repair::Model;
+use crate::slice_pass::*;
+use crate::utils::{display_state_update, CancelToken, MessageSink, ProgressCounter};
+use gladius_shared::error::SlicerErrors;
+use gladius_shared::messages::ProgressStage;
+use itertools::Itertools;
+use log::{debug, info};
+use ordered_float::OrderedFloat;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+pub mod arrange;
+pub mod bounds_checking;
+pub mod cache;
+pub mod calculation;
+pub mod command_pass;
+pub mod converter;
+pub mod input;
+pub mod optimizer;
+pub mod orient;
+pub mod plotter;
+pub mod repair;
+pub mod server;
+pub mod slice_pass;
+mod slicer;
+pub mod slicing;
+#[cfg(test)]
+mod test_utils;
+pub mod tower;
+pub mod utils;
+
+pub use slicer::{SliceResult, Slicer};
+
+///Slice the models into the optimized commands of the print
+pub fn generate_commands(
+    models: &[Model],
+    infos: &[ObjectInfo],
+    settings: &Settings,
+    cache: &mut SliceCache,
+    cancel: &CancelToken,
+    messages: MessageSink,
+) -> Result<Vec<Command>, SlicerErrors> {
+    let objects = cache.get_perimeters(models, infos, settings, cancel, messages)?;
+
+    let mut moves = generate_moves(objects, settings, cancel, messages)?;
+
+    check_moves_bounds(&moves, settings)?;
+
+    display_state_update("Optimizing", messages);
+    debug!("Optimizing {} Moves", moves.len());
+
+    OptimizePass::pass(&mut moves, settings);
+    display_state_update("Slowing Layer Down", messages);
+
+    SlowDownLayerPass::pass(&mut moves, settings);
+
+    Ok(moves)
+}
+
+///Run the passes that add supports, skirt, brim and perimeters to the sliced objects
+pub fn generate_perimeters(
+    objects: &mut Vec<Object>,
+    settings: &Settings,
+    cancel: &CancelToken,
+    messages: MessageSink,
+) -> Result<(), SlicerErrors> {
+    //Creates Support Towers
+    SupportTowerPass::pass(objects, settings, cancel, messages)?;
+
+    //Adds a skirt
+    SkirtPass::pass(objects, settings, cancel, messages)?;
+
+    //Adds a brim
+    BrimPass::pass(objects, settings, cancel, messages)?;
+
+    let v: Result<Vec<()>, SlicerErrors> = objects
+        .par_iter_mut()
+        .enumerate()
+        .map(|(index, object)| {
+            let slices = &mut object.layers;
+            let settings = object.settings.as_ref().unwrap_or(settings);
+
+            //Shrink layer
+            ShrinkPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Handle Perimeters
+            PerimeterPass::pass(slices, index, settings, cancel, messages)
+        })
+        .collect();
+
+    v?;
+
+    Ok(())
+}
+
+///Fill the remaining areas of the objects and convert them into commands
+pub fn generate_moves(
+    mut objects: Vec<Object>,
+    settings: &Settings,
+    cancel: &CancelToken,
+    messages: MessageSink,
+) -> Result<Vec<Command>, SlicerErrors> {
+    let progress = ProgressCounter::new(ProgressStage::Objects, None, objects.len(), messages);
+
+    let v: Result<Vec<()>, SlicerErrors> = objects
+        .par_iter_mut()
+        .enumerate()
+        .map(|(index, object)| {
+            let slices = &mut object.layers;
+            let settings = object.settings.as_ref().unwrap_or(settings);
+
+            //Handle Bridging
+            BridgingPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Handle Top Layer
+            TopLayerPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Handle Top And Bottom Layers
+            TopAndBottomLayersPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Handle Support
+            SupportPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Lightning Infill
+            LightningFillPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Adaptive Cubic Infill
+            AdaptiveCubicFillPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Fill Remaining areas
+            FillAreaPass::pass(slices, index, settings, cancel, messages)?;
+
+            //Order the move chains
+            OrderPass::pass(slices, index, settings, cancel, messages)?;
+
+            progress.increment();
+            Ok(())
+        })
+        .collect();
+
+    v?;
+
+    Ok(convert_objects_into_moves(objects, settings))
+}
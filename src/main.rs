@@ -2,51 +2,16 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use clap::{load_yaml, App};
-use gladius_shared::loader::*;
-use gladius_shared::types::*;
-
-use crate::plotter::convert_objects_into_moves;
-use crate::tower::*;
-use geo::*;
-use gladius_shared::settings::{PartialSettings, Settings, SettingsValidationResult};
-use std::fs::File;
-
-use std::ffi::OsStr;
-use std::path::Path;
-
-use crate::bounds_checking::{check_model_bounds, check_moves_bounds};
-use crate::calculation::calculate_values;
-use crate::command_pass::{CommandPass, OptimizePass, SlowDownLayerPass};
-use crate::converter::*;
-use crate::input::files_input;
-use crate::plotter::polygon_operations::PolygonOperations;
-use crate::slice_pass::*;
-use crate::slicing::*;
-use crate::utils::{
-    display_state_update, send_error_message, send_warning_message, show_error_message,
-    show_warning_message,
-};
 use gladius_shared::error::SlicerErrors;
 use gladius_shared::messages::Message;
-use itertools::Itertools;
-use log::{debug, info, LevelFilter};
-use ordered_float::OrderedFloat;
-use rayon::prelude::*;
+use gladius_slicer::server::run_server;
+use gladius_slicer::utils::{
+    print_message, send_error_message, set_message_format, show_error_message, MessageFormat,
+};
+use gladius_slicer::Slicer;
+use log::{info, LevelFilter};
 use simple_logger::SimpleLogger;
-use std::collections::HashMap;
-use std::io::BufWriter;
-
-mod bounds_checking;
-mod calculation;
-mod command_pass;
-mod converter;
-mod input;
-mod optimizer;
-mod plotter;
-mod slice_pass;
-mod slicing;
-mod tower;
-mod utils;
+use std::io::Write;
 
 fn main() {
     // The YAML file is found relative to the current file, similar to how modules are found
@@ -64,6 +29,15 @@ fn main() {
             .expect("Only call to build global");
     }
 
+    if matches.value_of("MESSAGE_FORMAT") == Some("json") {
+        set_message_format(MessageFormat::Json);
+    }
+
+    if matches.is_present("SERVER") {
+        handle_err_or_return(run_server(matches.value_of("SOCKET")), true);
+        return;
+    }
+
     let send_messages = matches.is_present("MESSAGES");
 
     if !send_messages {
@@ -82,60 +56,30 @@ fn main() {
             .expect("Only Logger Setup");
     }
 
-    display_state_update("Loading Inputs", send_messages);
-    let (models, settings) = handle_err_or_return(
-        files_input(
-            matches.value_of("SETTINGS"),
-            matches
-                .values_of("INPUT")
-                .map(|values| values.map(|v| v.to_string()).collect()),
-        ),
-        send_messages,
-    );
-
-    handle_err_or_return(check_model_bounds(&models, &settings), send_messages);
-
-    handle_setting_validation(settings.validate_settings(), send_messages);
-
-    display_state_update("Creating Towers", send_messages);
-
-    let towers: Vec<TriangleTower> = handle_err_or_return(create_towers(&models), send_messages);
-
-    display_state_update("Slicing", send_messages);
-
-    let objects = handle_err_or_return(slice(&towers, &settings), send_messages);
-
-    display_state_update("Generating Moves", send_messages);
-
-    let mut moves = handle_err_or_return(
-        generate_moves(objects, &settings, send_messages),
-        send_messages,
-    );
-
-    handle_err_or_return(check_moves_bounds(&moves, &settings), send_messages);
-
-    display_state_update("Optimizing", send_messages);
-    debug!("Optimizing {} Moves", moves.len());
-
-    OptimizePass::pass(&mut moves, &settings);
-    display_state_update("Slowing Layer Down", send_messages);
-
-    SlowDownLayerPass::pass(&mut moves, &settings);
+    let mut slicer = Slicer::new()
+        .inputs(matches.values_of("INPUT").into_iter().flatten())
+        .project_settings(matches.is_present("PROJECT_SETTINGS"))
+        .arrange(matches.is_present("ARRANGE"))
+        .arrange_rotate(matches.is_present("ARRANGE_ROTATE"));
 
+    if let Some(settings_path) = matches.value_of("SETTINGS") {
+        slicer = slicer.settings_file(settings_path);
+    }
+    if let Some(file_path) = matches.value_of("EXPORT_3MF") {
+        slicer = slicer.export_3mf(file_path);
+    }
+    if let Some(file_path) = matches.value_of("OUTPUT") {
+        slicer = slicer.output_file(file_path);
+    }
     if send_messages {
-        let message = Message::Commands(moves.clone());
-        bincode::serialize_into(BufWriter::new(std::io::stdout()), &message)
-            .expect("Write Limit should not be hit");
+        slicer = slicer.on_message(print_message);
     }
-    display_state_update("Calculate Values", send_messages);
 
-    let cv = calculate_values(&moves, &settings);
+    let result = handle_err_or_return(slicer.slice(), send_messages);
 
-    if send_messages {
-        let message = Message::CalculatedValues(cv);
-        bincode::serialize_into(BufWriter::new(std::io::stdout()), &message)
-            .expect("Write Limit should not be hit");
-    } else {
+    if !send_messages {
+        let cv = &result.calculated_values;
+        let settings = &result.settings;
         let (hour, min, sec, _) = cv.get_hours_minutes_seconds_fract_time();
 
         info!(
@@ -155,96 +99,19 @@ fn main() {
         );
     }
 
-    display_state_update("Outputting G-code", send_messages);
-    //Output the GCode
-    if let Some(file_path) = matches.value_of("OUTPUT") {
-        //Output to file
-        debug!("Converting {} Moves", moves.len());
-        handle_err_or_return(
-            convert(
-                &moves,
-                &settings,
-                &mut handle_err_or_return(
-                    File::create(file_path).map_err(|_| SlicerErrors::FileCreateError {
-                        filepath: file_path.to_string(),
-                    }),
-                    send_messages,
-                ),
-            )
-            .map_err(|_| SlicerErrors::FileWriteError {
-                filepath: file_path.to_string(),
-            }),
-            send_messages,
-        );
-    } else if send_messages {
-        //Output as message
-        let mut gcode: Vec<u8> = Vec::new();
-        convert(&moves, &settings, &mut gcode).expect("Writing to Vec shouldn't fail");
-        let message = Message::GCode(
-            String::from_utf8(gcode).expect("All write occur from write macro so should be utf8"),
-        );
-        bincode::serialize_into(BufWriter::new(std::io::stdout()), &message)
-            .expect("Write Limit should not be hit");
-    } else {
-        //Output to stdout
-        let stdout = std::io::stdout();
-        let mut stdio_lock = stdout.lock();
-        debug!("Converting {} Moves", moves.len());
-        convert(&moves, &settings, &mut stdio_lock).expect("Writing to STDOUT shouldn't fail");
-    };
-}
-
-fn generate_moves(
-    mut objects: Vec<Object>,
-    settings: &Settings,
-    send_messages: bool,
-) -> Result<Vec<Command>, SlicerErrors> {
-    //Creates Support Towers
-    SupportTowerPass::pass(&mut objects, settings, send_messages);
-
-    //Adds a skirt
-    SkirtPass::pass(&mut objects, settings, send_messages);
-
-    //Adds a brim
-    BrimPass::pass(&mut objects, settings, send_messages);
-
-    let v: Result<Vec<()>, SlicerErrors> = objects
-        .par_iter_mut()
-        .map(|object| {
-            let slices = &mut object.layers;
-
-            //Shrink layer
-            ShrinkPass::pass(slices, settings, send_messages)?;
-
-            //Handle Perimeters
-            PerimeterPass::pass(slices, settings, send_messages)?;
-
-            //Handle Bridging
-            BridgingPass::pass(slices, settings, send_messages)?;
-
-            //Handle Top Layer
-            TopLayerPass::pass(slices, settings, send_messages)?;
-
-            //Handle Top And Bottom Layers
-            TopAndBottomLayersPass::pass(slices, settings, send_messages)?;
-
-            //Handle Support
-            SupportPass::pass(slices, settings, send_messages)?;
-
-            //Lightning Infill
-            LightningFillPass::pass(slices, settings, send_messages)?;
-
-            //Fill Remaining areas
-            FillAreaPass::pass(slices, settings, send_messages)?;
-
-            //Order the move chains
-            OrderPass::pass(slices, settings, send_messages)
-        })
-        .collect();
-
-    v?;
-
-    Ok(convert_objects_into_moves(objects, settings))
+    //Output the GCode if it was not written to a file
+    if let Some(gcode) = result.gcode {
+        if send_messages {
+            //Output as message
+            print_message(&Message::GCode(gcode));
+        } else {
+            //Output to stdout
+            std::io::stdout()
+                .lock()
+                .write_all(gcode.as_bytes())
+                .expect("Writing to STDOUT shouldn't fail");
+        }
+    }
 }
 
 fn handle_err_or_return<T>(res: Result<T, SlicerErrors>, send_message: bool) -> T {
@@ -260,24 +127,3 @@ fn handle_err_or_return<T>(res: Result<T, SlicerErrors>, send_message: bool) ->
         }
     }
 }
-
-fn handle_setting_validation(res: SettingsValidationResult, send_message: bool) {
-    match res {
-        SettingsValidationResult::NoIssue => {}
-        SettingsValidationResult::Warning(slicer_warning) => {
-            if send_message {
-                send_warning_message(slicer_warning)
-            } else {
-                show_warning_message(slicer_warning)
-            }
-        }
-        SettingsValidationResult::Error(slicer_error) => {
-            if send_message {
-                send_error_message(slicer_error)
-            } else {
-                show_error_message(slicer_error)
-            }
-            std::process::exit(-1);
-        }
-    }
-}
@@ -9,7 +9,7 @@ use itertools::Itertools;
 pub fn unary_optimizer(cmds: &mut Vec<Command>) {
     cmds.retain(|cmd| match cmd {
         Command::MoveTo { .. } => true,
-        Command::MoveAndExtrude { start, end, .. } => start != end,
+        Command::MoveAndExtrude { start, end, z, .. } => start != end || z.is_some(),
         Command::LayerChange { .. } => true,
         Command::ChangeObject { .. } => true,
         Command::SetState { new_state } => {
@@ -39,19 +39,21 @@ pub fn binary_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
                     Command::MoveAndExtrude {
                         start: f_start,
                         end: f_end,
+                        z: f_z,
                         thickness: f_thick,
                         width: f_width,
                     },
                     Command::MoveAndExtrude {
                         start: s_start,
                         end: s_end,
+                        z: s_z,
                         thickness: s_thick,
                         width: s_width,
                     },
                 ) => {
                     current_pos = s_end;
 
-                    if f_end == s_start && s_width == f_width && s_thick == f_thick {
+                    if f_end == s_start && s_width == f_width && s_thick == f_thick && f_z == s_z {
                         let det = (((f_start.x - s_start.x) * (s_start.y - s_end.y))
                             - ((f_start.y - s_start.y) * (s_start.x - s_end.x)))
                             .abs();
@@ -62,24 +64,29 @@ pub fn binary_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
                             return Ok(Command::MoveAndExtrude {
                                 start: f_start,
                                 end: s_end,
+                                z: s_z,
                                 thickness: f_thick,
                                 width: s_width,
                             });
                         }
                     }
                 }
-                (Command::MoveTo { .. }, Command::MoveTo { end: s_end }) => {
+                (Command::MoveTo { z: f_z, .. }, Command::MoveTo { end: s_end, z: s_z }) => {
                     current_pos = s_end;
-                    return Ok(Command::MoveTo { end: s_end });
+                    //keep the height of the first move if the second doesn't change it
+                    return Ok(Command::MoveTo {
+                        end: s_end,
+                        z: s_z.or(f_z),
+                    });
                 }
                 (Command::Delay { msec: t1 }, Command::Delay { msec: t2 }) => {
                     //merge back to back delays
                     return Ok(Command::Delay { msec: t1 + t2 });
                 }
 
-                (Command::ChangeObject { .. }, Command::ChangeObject { object }) => {
+                (Command::ChangeObject { .. }, Command::ChangeObject { object, name }) => {
                     // skip an object change followed by another change
-                    return Ok(Command::ChangeObject { object });
+                    return Ok(Command::ChangeObject { object, name });
                 }
 
                 (
@@ -94,7 +101,7 @@ pub fn binary_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
                     Command::SetState {
                         new_state: mut f_state,
                     },
-                    Command::MoveTo { end },
+                    Command::MoveTo { end, z },
                 ) => {
                     if f_state.retract == RetractionType::Retract
                         && Line::new(current_pos, end).euclidean_length()
@@ -107,7 +114,7 @@ pub fn binary_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
 
                         return Err((
                             Command::SetState { new_state: f_state },
-                            Command::MoveTo { end },
+                            Command::MoveTo { end, z },
                         ));
                     } else if let RetractionType::MoveRetract(_) = f_state.retract {
                         if Line::new(current_pos, end).euclidean_length()
@@ -120,7 +127,7 @@ pub fn binary_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
 
                             return Err((
                                 Command::SetState { new_state: f_state },
-                                Command::MoveTo { end },
+                                Command::MoveTo { end, z },
                             ));
                         }
                     } else {
@@ -137,7 +144,7 @@ pub fn binary_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
                 ) => {
                     current_pos = s_end;
                 }
-                (_, Command::MoveTo { end: s_end }) => {
+                (_, Command::MoveTo { end: s_end, .. }) => {
                     current_pos = s_end;
                 }
                 (_, _) => {}
@@ -165,8 +172,12 @@ pub fn arc_optomizer(cmds: &mut Vec<Command>) {
 
     for (wt, group) in &cmds.iter().enumerate().group_by(|cmd| {
         //println!("{}",cmd.0);
+        //moves that change height can't be converted into planar arcs
         if let Command::MoveAndExtrude {
-            thickness, width, ..
+            thickness,
+            width,
+            z: None,
+            ..
         } = cmd.1
         {
             Some((thickness, width))
@@ -274,6 +285,7 @@ pub fn arc_optomizer(cmds: &mut Vec<Command>) {
                 x: center.0,
                 y: center.1,
             },
+            z: None,
             thickness,
             width,
         };
@@ -437,6 +449,7 @@ mod tests {
             .map(|(start, end)| Command::MoveAndExtrude {
                 start,
                 end,
+                z: None,
                 thickness: 0.3,
                 width: 0.4,
             })
@@ -479,6 +492,7 @@ mod tests {
                 .map(|(start, end)| Command::MoveAndExtrude {
                     start,
                     end,
+                    z: None,
                     thickness: 0.3,
                     width: 0.4,
                 }),
@@ -506,4 +520,47 @@ mod tests {
             panic!("Command should be an arc")
         }
     }
+
+    #[test]
+    fn binary_optimizer_keeps_z() {
+        let mut commands = vec![
+            Command::MoveTo {
+                end: Coord { x: 1.0, y: 1.0 },
+                z: Some(2.0),
+            },
+            Command::MoveTo {
+                end: Coord { x: 2.0, y: 1.0 },
+                z: None,
+            },
+            Command::MoveAndExtrude {
+                start: Coord { x: 2.0, y: 1.0 },
+                end: Coord { x: 3.0, y: 1.0 },
+                z: Some(2.1),
+                thickness: 0.3,
+                width: 0.4,
+            },
+            Command::MoveAndExtrude {
+                start: Coord { x: 3.0, y: 1.0 },
+                end: Coord { x: 4.0, y: 1.0 },
+                z: Some(2.2),
+                thickness: 0.3,
+                width: 0.4,
+            },
+        ];
+
+        binary_optimizer(&mut commands, &Settings::default());
+
+        assert_eq!(commands.len(), 3);
+        assert_eq!(
+            commands[0],
+            Command::MoveTo {
+                end: Coord { x: 2.0, y: 1.0 },
+                z: Some(2.0),
+            }
+        );
+        assert!(matches!(
+            commands[2],
+            Command::MoveAndExtrude { z: Some(z), .. } if z == 2.2
+        ));
+    }
 }
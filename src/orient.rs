@@ -0,0 +1,264 @@
+use gladius_shared::types::{IndexedTriangle, OrientationGoal, Vertex};
+use nalgebra::{Rotation3, Unit, Vector3};
+use std::collections::HashMap;
+
+///The number of most common face directions tried as the downward direction
+const MAX_CANDIDATES: usize = 100;
+
+///Faces within this distance of the lowest point in mm are on the bed
+const CONTACT_DISTANCE: f64 = 0.01;
+
+///Faces within this angle of straight down in radians are flat on the bed
+const CONTACT_ANGLE: f64 = 0.01;
+
+///A triangle of the models with its unit normal and area
+struct Face {
+    points: [Vector3<f64>; 3],
+    normal: Vector3<f64>,
+    area: f64,
+}
+
+///The overhang and contact area when a direction is placed down
+struct OrientationScore {
+    overhang: f64,
+    contact: f64,
+}
+
+impl OrientationScore {
+    fn is_better_than(&self, other: &OrientationScore, goal: OrientationGoal) -> bool {
+        //Small differences are ignored so the original orientation is kept when equal
+        let better = |a: f64, b: f64| a > b + 0.0001 * (a.abs() + b.abs()).max(1.0);
+
+        match goal {
+            OrientationGoal::MinimalSupport => {
+                better(other.overhang, self.overhang)
+                    || (!better(self.overhang, other.overhang)
+                        && better(self.contact, other.contact))
+            }
+            OrientationGoal::MaximalContact => {
+                better(self.contact, other.contact)
+                    || (!better(other.contact, self.contact)
+                        && better(other.overhang, self.overhang))
+            }
+        }
+    }
+}
+
+/// Find the rotation that best places the models on the bed for the goal.
+///
+/// The candidate downward directions are the axes and the most common face normals weighted by area.
+/// Faces pointing down more steeply than the max overhang angle from vertical that are not on the bed
+/// count as overhangs. Returns the rotation as euler angles in radians (roll, pitch, yaw).
+pub fn find_orientation(
+    models: &[(&[Vertex], &[IndexedTriangle])],
+    goal: OrientationGoal,
+    max_overhang_angle: f64,
+) -> (f64, f64, f64) {
+    let faces: Vec<Face> = models
+        .iter()
+        .flat_map(|(vertices, triangles)| {
+            triangles.iter().filter_map(move |triangle| {
+                let [a, b, c] = triangle.verts.map(|index| {
+                    let v = vertices[index];
+                    Vector3::new(v.x, v.y, v.z)
+                });
+                let cross = (b - a).cross(&(c - a));
+                let length = cross.norm();
+
+                if length > 0.0 {
+                    Some(Face {
+                        points: [a, b, c],
+                        normal: cross / length,
+                        area: length / 2.0,
+                    })
+                } else {
+                    None
+                }
+            })
+        })
+        .collect();
+
+    //Group similar normals to find the directions covering the most area
+    let mut normal_areas: HashMap<(i64, i64, i64), (Vector3<f64>, f64)> = HashMap::new();
+    for Face { normal, area, .. } in &faces {
+        let key = (
+            (normal.x * 1000.0).round() as i64,
+            (normal.y * 1000.0).round() as i64,
+            (normal.z * 1000.0).round() as i64,
+        );
+        let entry = normal_areas.entry(key).or_insert((*normal, 0.0));
+        entry.1 += area;
+    }
+
+    let mut common_normals: Vec<(Vector3<f64>, f64)> = normal_areas.into_values().collect();
+    common_normals.sort_by(|a, b| b.1.partial_cmp(&a.1).expect("No NAN areas are allowed"));
+
+    //The current orientation is first so it is kept unless another is better
+    let axes = [
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 0.0, 1.0),
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(-1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        Vector3::new(0.0, -1.0, 0.0),
+    ];
+    let candidates = axes.iter().copied().chain(
+        common_normals
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(|(normal, _)| normal),
+    );
+
+    //The cosine of the angle to straight down is the sine of the overhang angle from vertical
+    let overhang_facing_down = max_overhang_angle.to_radians().sin();
+    let contact_cos = CONTACT_ANGLE.cos();
+
+    let mut best: Option<(Vector3<f64>, OrientationScore)> = None;
+
+    for down in candidates {
+        let lowest = faces
+            .iter()
+            .flat_map(|face| face.points.iter())
+            .map(|p| p.dot(&down))
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let mut score = OrientationScore {
+            overhang: 0.0,
+            contact: 0.0,
+        };
+
+        for face in &faces {
+            let facing_down = face.normal.dot(&down);
+            let on_bed = face
+                .points
+                .iter()
+                .all(|p| lowest - p.dot(&down) < CONTACT_DISTANCE);
+
+            if on_bed && facing_down > contact_cos {
+                score.contact += face.area;
+            } else if facing_down > overhang_facing_down {
+                score.overhang += face.area;
+            }
+        }
+
+        if best
+            .as_ref()
+            .is_none_or(|(_, best_score)| score.is_better_than(best_score, goal))
+        {
+            best = Some((down, score));
+        }
+    }
+
+    let down = best.map(|(down, _)| down).unwrap_or_else(|| -Vector3::z());
+
+    rotation_to_bed(&down)
+}
+
+/// Find the rotation that points the direction straight down onto the bed.
+///
+/// Returns the rotation as euler angles in radians (roll, pitch, yaw).
+pub fn rotation_to_bed(down: &Vector3<f64>) -> (f64, f64, f64) {
+    //Directions pointing straight up have no single rotation between them so flip over x
+    Rotation3::rotation_between(down, &-Vector3::z())
+        .unwrap_or_else(|| {
+            Rotation3::from_axis_angle(&Unit::new_normalize(Vector3::x()), std::f64::consts::PI)
+        })
+        .euler_angles()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gladius_shared::types::Transform;
+
+    ///A square pyramid with its tip at the origin pointing down
+    fn upside_down_pyramid() -> (Vec<Vertex>, Vec<IndexedTriangle>) {
+        let vertices = vec![
+            Vertex {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vertex {
+                x: -10.0,
+                y: -10.0,
+                z: 10.0,
+            },
+            Vertex {
+                x: 10.0,
+                y: -10.0,
+                z: 10.0,
+            },
+            Vertex {
+                x: 10.0,
+                y: 10.0,
+                z: 10.0,
+            },
+            Vertex {
+                x: -10.0,
+                y: 10.0,
+                z: 10.0,
+            },
+        ];
+        let triangles = [
+            [0, 2, 1],
+            [0, 3, 2],
+            [0, 4, 3],
+            [0, 1, 4],
+            [1, 2, 3],
+            [1, 3, 4],
+        ]
+        .iter()
+        .map(|verts| IndexedTriangle { verts: *verts })
+        .collect();
+
+        (vertices, triangles)
+    }
+
+    fn lowest_points_after_rotation(vertices: &[Vertex], angles: (f64, f64, f64)) -> usize {
+        let transform = Transform::new_rotation_transform(angles.0, angles.1, angles.2);
+        let rotated: Vec<Vertex> = vertices.iter().map(|v| &transform * *v).collect();
+        let lowest = rotated.iter().map(|v| v.z).fold(f64::INFINITY, f64::min);
+
+        rotated.iter().filter(|v| v.z - lowest < 0.000001).count()
+    }
+
+    #[test]
+    fn pyramid_placed_on_base() {
+        let (vertices, triangles) = upside_down_pyramid();
+
+        for goal in [
+            OrientationGoal::MinimalSupport,
+            OrientationGoal::MaximalContact,
+        ]
+        .iter()
+        {
+            let angles = find_orientation(&[(&vertices, &triangles)], *goal, 45.0);
+
+            //The four base corners are on the bed
+            assert_eq!(lowest_points_after_rotation(&vertices, angles), 4);
+        }
+    }
+
+    #[test]
+    fn flat_model_unchanged() {
+        let (vertices, triangles) = upside_down_pyramid();
+        let angles = find_orientation(
+            &[(&vertices, &triangles)],
+            OrientationGoal::MaximalContact,
+            45.0,
+        );
+        let transform = Transform::new_rotation_transform(angles.0, angles.1, angles.2);
+        let placed: Vec<Vertex> = vertices.iter().map(|v| &transform * *v).collect();
+
+        let angles = find_orientation(
+            &[(&placed, &triangles)],
+            OrientationGoal::MaximalContact,
+            45.0,
+        );
+
+        assert!(angles.0.abs() < 0.000001);
+        assert!(angles.1.abs() < 0.000001);
+        assert!(angles.2.abs() < 0.000001);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::plotter::infill::{
+    clipped_line_chains, cubic_line_offset, cubic_line_spacing, partial_fill_area, CUBIC_ANGLES,
+};
+use crate::utils::CancelToken;
+use crate::PolygonOperations;
+use geo::prelude::*;
+use geo::*;
+use gladius_shared::error::SlicerErrors;
+use gladius_shared::types::{MoveType, Slice};
+use rayon::prelude::*;
+
+///Number of times the cells can double in size away from the surfaces
+const MAX_CELL_LEVEL: u32 = 3;
+
+///A cube of the octree that is not divided further, level 0 cells are the smallest
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    min: Coord<f64>,
+    bottom: f64,
+    size: f64,
+    level: u32,
+}
+
+impl Cell {
+    fn square(&self) -> Polygon<f64> {
+        Rect::new(
+            self.min,
+            Coord {
+                x: self.min.x + self.size,
+                y: self.min.y + self.size,
+            },
+        )
+        .to_polygon()
+    }
+
+    fn contains_height(&self, height: f64) -> bool {
+        self.bottom <= height && height < self.bottom + self.size
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Containment {
+    Inside,
+    Outside,
+    Boundary,
+}
+
+///Fill the remaining area with cubic infill that gets sparser away from the walls and top surfaces.
+///The cells of an octree built from the main polygons are divided until they are the size of a cubic cell
+///or entirely inside the object. Each doubling of the cell size keeps every second cubic line so the
+///infill percentage halves while the remaining lines continue straight into the denser cells
+pub fn adaptive_cubic_infill(
+    slices: &mut [Slice],
+    cancel: &CancelToken,
+) -> Result<(), SlicerErrors> {
+    let Some(first) = slices.first() else {
+        return Ok(());
+    };
+
+    if first.layer_settings.infill_percentage < f64::EPSILON {
+        //Left for the fill pass that skips empty infill
+        return Ok(());
+    }
+
+    let min_size = cubic_line_spacing(
+        &first.layer_settings,
+        first.layer_settings.infill_percentage,
+    );
+    let cells = octree_cells(slices, min_size, cancel)?;
+
+    slices.par_iter_mut().try_for_each(|slice| {
+        cancel.check()?;
+
+        let height = slice.get_height();
+        let settings = &slice.layer_settings;
+        let spacing = cubic_line_spacing(settings, settings.infill_percentage);
+        let offset = cubic_line_offset(height);
+        let fill_area = partial_fill_area(&slice.remaining_area, settings);
+
+        //The area each level of lines is printed in, excluding the cells too large for the level
+        let level_areas: Vec<MultiPolygon<f64>> = (0..=MAX_CELL_LEVEL)
+            .map(|level| {
+                fill_area.difference_with(&MultiPolygon(
+                    cells
+                        .iter()
+                        .filter(|cell| cell.level > level && cell.contains_height(height))
+                        .map(Cell::square)
+                        .collect(),
+                ))
+            })
+            .collect();
+
+        for angle in CUBIC_ANGLES {
+            let rotated_areas: Vec<MultiPolygon<f64>> = level_areas
+                .iter()
+                .map(|area| area.rotate_around_point(angle, Point(Coord::zero())))
+                .collect();
+
+            let Some(bounds) = rotated_areas.last().and_then(|area| area.bounding_rect()) else {
+                continue;
+            };
+
+            //Lines at the same positions as cubic infill, grouped by the largest cells they are kept in
+            let mut level_lines = vec![vec![]; rotated_areas.len()];
+
+            for line in ((bounds.min().y + offset) / spacing).floor() as i64
+                ..=((bounds.max().y + offset) / spacing).ceil() as i64
+            {
+                let y = line as f64 * spacing - offset;
+                let mut points = vec![
+                    Coord {
+                        x: bounds.min().x - spacing,
+                        y,
+                    },
+                    Coord {
+                        x: bounds.max().x + spacing,
+                        y,
+                    },
+                ];
+
+                //Alternate directions so the end of one line is near the start of the next
+                if line.rem_euclid(2) == 1 {
+                    points.reverse();
+                }
+
+                let level = (line.trailing_zeros()).min(MAX_CELL_LEVEL) as usize;
+                level_lines[level].push(LineString::from(points));
+            }
+
+            for (area, lines) in rotated_areas.iter().zip(level_lines) {
+                if lines.is_empty() {
+                    continue;
+                }
+
+                for mut chain in clipped_line_chains(area, lines, settings, MoveType::Infill) {
+                    chain.rotate(-angle.to_radians());
+                    slice.chains.push(chain);
+                }
+            }
+        }
+
+        slice.remaining_area = MultiPolygon(vec![]);
+        Ok(())
+    })
+}
+
+///The leaves of an octree with cells from the smallest size up to the largest level covering the slices
+fn octree_cells(
+    slices: &[Slice],
+    min_size: f64,
+    cancel: &CancelToken,
+) -> Result<Vec<Cell>, SlicerErrors> {
+    let root_size = min_size * 2_u32.pow(MAX_CELL_LEVEL) as f64;
+
+    let Some(bounds) = MultiPolygon(
+        slices
+            .iter()
+            .flat_map(|slice| slice.main_polygon.0.iter().cloned())
+            .collect(),
+    )
+    .bounding_rect() else {
+        return Ok(vec![]);
+    };
+    let top = slices.last().map(|slice| slice.top_height).unwrap_or(0.0);
+
+    //Roots are anchored to the origin so the cells line up with the cubic pattern
+    let roots: Vec<Cell> = ((bounds.min().x / root_size).floor() as i64
+        ..(bounds.max().x / root_size).ceil() as i64)
+        .flat_map(|x| {
+            ((bounds.min().y / root_size).floor() as i64
+                ..(bounds.max().y / root_size).ceil() as i64)
+                .flat_map(move |y| {
+                    (0..(top / root_size).ceil() as i64).map(move |z| Cell {
+                        min: Coord {
+                            x: x as f64 * root_size,
+                            y: y as f64 * root_size,
+                        },
+                        bottom: z as f64 * root_size,
+                        size: root_size,
+                        level: MAX_CELL_LEVEL,
+                    })
+                })
+        })
+        .collect();
+
+    let leaves: Result<Vec<Vec<Cell>>, SlicerErrors> = roots
+        .into_par_iter()
+        .map(|root| {
+            let mut leaves = vec![];
+            subdivide(slices, root, &mut leaves, cancel)?;
+            Ok(leaves)
+        })
+        .collect();
+
+    Ok(leaves?.into_iter().flatten().collect())
+}
+
+///Add the leaves of the cell, dividing cells that cross the surface of the object
+fn subdivide(
+    slices: &[Slice],
+    cell: Cell,
+    leaves: &mut Vec<Cell>,
+    cancel: &CancelToken,
+) -> Result<(), SlicerErrors> {
+    cancel.check()?;
+
+    match containment(slices, &cell) {
+        Containment::Outside => {}
+        Containment::Inside => leaves.push(cell),
+        Containment::Boundary if cell.level == 0 => leaves.push(cell),
+        Containment::Boundary => {
+            let size = cell.size / 2.0;
+
+            for (x, y, z) in (0..8).map(|corner| (corner & 1, (corner >> 1) & 1, (corner >> 2) & 1))
+            {
+                subdivide(
+                    slices,
+                    Cell {
+                        min: Coord {
+                            x: cell.min.x + x as f64 * size,
+                            y: cell.min.y + y as f64 * size,
+                        },
+                        bottom: cell.bottom + z as f64 * size,
+                        size,
+                        level: cell.level - 1,
+                    },
+                    leaves,
+                    cancel,
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+///Whether the cell and a margin of half its size around it is inside the object on every layer,
+///outside on all of them or crosses the surface
+fn containment(slices: &[Slice], cell: &Cell) -> Containment {
+    let margin = cell.size / 2.0;
+    let square = MultiPolygon(vec![Rect::new(
+        Coord {
+            x: cell.min.x - margin,
+            y: cell.min.y - margin,
+        },
+        Coord {
+            x: cell.min.x + cell.size + margin,
+            y: cell.min.y + cell.size + margin,
+        },
+    )
+    .to_polygon()]);
+    let square_area = square.unsigned_area();
+
+    //Only the margin above is checked as the cells below support nothing
+    let bottom = cell.bottom;
+    let top = cell.bottom + cell.size + margin;
+
+    //Above the last layer is outside so the cells under the top surface are small
+    let mut outside = slices.last().is_none_or(|slice| slice.top_height < top);
+    let mut inside = false;
+
+    for slice in slices
+        .iter()
+        .filter(|slice| slice.top_height > bottom && slice.bottom_height < top)
+    {
+        let area = square
+            .intersection_with(&slice.main_polygon)
+            .unsigned_area();
+
+        if area > square_area * (1.0 - 1e-6) {
+            inside = true;
+        } else if area < square_area * 1e-6 {
+            outside = true;
+        } else {
+            return Containment::Boundary;
+        }
+
+        if inside && outside {
+            return Containment::Boundary;
+        }
+    }
+
+    match (inside, outside) {
+        (true, false) => Containment::Inside,
+        (false, _) => Containment::Outside,
+        (true, true) => Containment::Boundary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gladius_shared::settings::Settings;
+
+    fn cube_slices(settings: &Settings) -> Vec<Slice> {
+        (0..100)
+            .map(|layer| {
+                Slice::from_single_point_loop(
+                    [(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)]
+                        .iter()
+                        .copied(),
+                    layer as f64,
+                    layer as f64 + 1.0,
+                    layer,
+                    settings,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cells_grow_away_from_surfaces() {
+        let settings = Settings::default();
+        let slices = cube_slices(&settings);
+
+        let cells = octree_cells(&slices, 5.0, &CancelToken::default()).expect("Not cancelled");
+
+        //Cells near the walls and top are the smallest
+        for cell in &cells {
+            let distance = [
+                cell.min.x,
+                cell.min.y,
+                100.0 - (cell.min.x + cell.size),
+                100.0 - (cell.min.y + cell.size),
+                100.0 - (cell.bottom + cell.size),
+            ]
+            .iter()
+            .copied()
+            .fold(f64::INFINITY, f64::min);
+
+            if distance < 2.5 {
+                assert_eq!(cell.level, 0);
+            }
+        }
+
+        assert!(cells.iter().any(|cell| cell.level == MAX_CELL_LEVEL));
+
+        //The cells cover the object without overlapping
+        let volume: f64 = cells
+            .iter()
+            .map(|cell| {
+                let overlap =
+                    |min: f64, size: f64| ((min + size).min(100.0) - min.max(0.0)).max(0.0);
+                overlap(cell.min.x, cell.size)
+                    * overlap(cell.min.y, cell.size)
+                    * overlap(cell.bottom, cell.size)
+            })
+            .sum();
+        assert!((volume - 1_000_000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cancelled_infill_stops() {
+        let settings = Settings::default();
+        let mut slices = cube_slices(&settings);
+
+        let cancel = CancelToken::default();
+        cancel.cancel();
+
+        assert!(matches!(
+            octree_cells(&slices, 5.0, &cancel),
+            Err(SlicerErrors::Cancelled)
+        ));
+        assert!(matches!(
+            adaptive_cubic_infill(&mut slices, &cancel),
+            Err(SlicerErrors::Cancelled)
+        ));
+    }
+}
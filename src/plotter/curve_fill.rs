@@ -0,0 +1,328 @@
+use crate::plotter::infill::{clipped_line_chains, partial_fill_area};
+use geo::prelude::*;
+use geo::*;
+use gladius_shared::settings::LayerSettings;
+use gladius_shared::types::{MoveChain, MoveType};
+use std::f64::consts::PI;
+
+///Length of the chords approximating the spiral, in extrusion widths
+const ARCHIMEDEAN_CHORD_WIDTHS: f64 = 4.0;
+
+///Cells along each side of the grid the Hilbert curve runs through, the grid is centered on the origin
+const HILBERT_GRID_CELLS: u64 = 1 << 20;
+
+///Fill the polygon with a Hilbert curve spaced one extrusion width apart, clipped to the polygon.
+///Every region takes its part of the same curve through a fixed grid so neighbouring regions line up
+pub fn hilbert_curve_fill_polygon(
+    poly: &Polygon<f64>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+) -> Vec<MoveChain> {
+    let fill_area = partial_fill_area(poly, settings);
+
+    let Some(bounds) = fill_area.bounding_rect() else {
+        return vec![];
+    };
+
+    let spacing = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    let half = (HILBERT_GRID_CELLS / 2) as f64;
+    let cell = |value: f64| {
+        ((value / spacing).floor() + half).clamp(0.0, (HILBERT_GRID_CELLS - 1) as f64) as u64
+    };
+
+    let runs = hilbert_curve(
+        HILBERT_GRID_CELLS,
+        (cell(bounds.min().x), cell(bounds.min().y)),
+        (cell(bounds.max().x), cell(bounds.max().y)),
+    );
+
+    let lines = runs
+        .into_iter()
+        .filter(|run| run.len() > 1)
+        .map(|run| {
+            run.into_iter()
+                .map(|(x, y)| Coord {
+                    x: (x as f64 - half + 0.5) * spacing,
+                    y: (y as f64 - half + 0.5) * spacing,
+                })
+                .collect::<LineString<f64>>()
+        })
+        .collect();
+
+    clipped_line_chains(&fill_area, lines, settings, fill_type)
+}
+
+///Fill the polygon with an Archimedean spiral from the center of the polygon, spaced one extrusion
+///width apart and approximated by chords, clipped to the polygon
+pub fn archimedean_chords_fill_polygon(
+    poly: &Polygon<f64>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+) -> Vec<MoveChain> {
+    let fill_area = partial_fill_area(poly, settings);
+
+    let Some(bounds) = fill_area.bounding_rect() else {
+        return vec![];
+    };
+
+    let spacing = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+    let chord_length = ARCHIMEDEAN_CHORD_WIDTHS * spacing;
+
+    let center = bounds.center();
+    let max_radius = Line::new(center, bounds.max()).euclidean_length() + spacing;
+
+    //r = spacing * theta / 2pi so each turn is one spacing further out
+    let mut theta: f64 = 0.0;
+    let mut points = vec![center];
+
+    loop {
+        let radius = spacing * theta / (2.0 * PI);
+
+        if radius > max_radius {
+            break;
+        }
+
+        //Chords of about the same length, with at least 8 per turn near the center
+        theta += (chord_length / radius.max(f64::EPSILON)).min(PI / 4.0);
+
+        let radius = spacing * theta / (2.0 * PI);
+        let (sin, cos) = theta.sin_cos();
+        points.push(Coord {
+            x: center.x + radius * cos,
+            y: center.y + radius * sin,
+        });
+    }
+
+    clipped_line_chains(
+        &fill_area,
+        vec![LineString::from(points)],
+        settings,
+        fill_type,
+    )
+}
+
+///The corners of the parts of a Hilbert curve through a square grid of cells that lie between the
+///min and max cells, inclusive. The size must be a power of two.
+///A new run starts wherever the curve leaves the range and points along straight runs are left out
+fn hilbert_curve(size: u64, min: (u64, u64), max: (u64, u64)) -> Vec<Vec<(u64, u64)>> {
+    let mut runs: Vec<Vec<(u64, u64)>> = vec![];
+    let mut next_index = None;
+
+    //Each block of the curve is a square covering a range of indices, split until single cells
+    let mut blocks = vec![(0, size)];
+
+    while let Some((first_index, block_size)) = blocks.pop() {
+        let corner = hilbert_point(size, first_index);
+        let x = corner.0 - corner.0 % block_size;
+        let y = corner.1 - corner.1 % block_size;
+
+        if x > max.0 || x + block_size <= min.0 || y > max.1 || y + block_size <= min.1 {
+            continue;
+        }
+
+        if block_size > 1 {
+            let quarter = block_size * block_size / 4;
+            blocks.extend(
+                (0..4)
+                    .rev()
+                    .map(|q| (first_index + q * quarter, block_size / 2)),
+            );
+            continue;
+        }
+
+        if next_index != Some(first_index) {
+            runs.push(vec![]);
+        }
+        next_index = Some(first_index + 1);
+
+        if let Some(points) = runs.last_mut() {
+            if let [.., before, last] = points.as_slice() {
+                //Replace the last point when it continues in the same direction
+                let same_x = before.0 == last.0 && last.0 == corner.0;
+                let same_y = before.1 == last.1 && last.1 == corner.1;
+
+                if same_x || same_y {
+                    points.pop();
+                }
+            }
+
+            points.push(corner);
+        }
+    }
+
+    runs
+}
+
+///The cell at the distance along a Hilbert curve through a square grid of cells
+fn hilbert_point(size: u64, index: u64) -> (u64, u64) {
+    let (mut x, mut y) = (0, 0);
+    let mut remaining = index;
+    let mut scale = 1;
+
+    while scale < size {
+        let right = (remaining / 2) & 1;
+        let up = (remaining ^ right) & 1;
+
+        //Rotate the quadrant so the sub curves join up
+        if up == 0 {
+            if right == 1 {
+                x = scale - 1 - x;
+                y = scale - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += scale * right;
+        y += scale * up;
+        remaining /= 4;
+        scale *= 2;
+    }
+
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gladius_shared::settings::Settings;
+
+    #[test]
+    fn hilbert_curve_visits_every_cell_once() {
+        let size = 8;
+        let points: Vec<(u64, u64)> = (0..size * size)
+            .map(|index| hilbert_point(size, index))
+            .collect();
+
+        for (a, b) in points.iter().zip(points.iter().skip(1)) {
+            assert_eq!(a.0.abs_diff(b.0) + a.1.abs_diff(b.1), 1);
+        }
+
+        let mut sorted = points.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), points.len());
+
+        let runs = hilbert_curve(size, (0, 0), (size - 1, size - 1));
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].first(), points.first());
+        assert_eq!(runs[0].last(), points.last());
+        assert!(runs[0].len() < points.len());
+    }
+
+    #[test]
+    fn hilbert_runs_follow_the_whole_curve() {
+        let size = 16;
+        let (min, max) = ((3, 5), (10, 12));
+        let inside = |(x, y): (u64, u64)| min.0 <= x && x <= max.0 && min.1 <= y && y <= max.1;
+
+        //The cells of the whole curve in the range, split where the curve leaves it
+        let mut expected: Vec<Vec<(u64, u64)>> = vec![];
+        let mut was_inside = false;
+        for index in 0..size * size {
+            let point = hilbert_point(size, index);
+            if inside(point) {
+                if !was_inside {
+                    expected.push(vec![]);
+                }
+                expected.last_mut().expect("Run was started").push(point);
+            }
+            was_inside = inside(point);
+        }
+
+        let runs = hilbert_curve(size, min, max);
+        assert_eq!(runs.len(), expected.len());
+
+        for (run, cells) in runs.iter().zip(&expected) {
+            assert_eq!(run.first(), cells.first());
+            assert_eq!(run.last(), cells.last());
+
+            //Corners are in the same order as on the whole curve
+            let mut remaining = cells.iter();
+            for corner in run {
+                assert!(remaining.any(|cell| cell == corner));
+            }
+        }
+    }
+
+    #[test]
+    fn hilbert_fill_of_part_matches_whole() {
+        let settings = Settings::default().get_layer_settings(0, 0.2);
+        let square = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 30.0, y: 30.0 }).to_polygon();
+        let part = Rect::new(Coord { x: 10.0, y: 10.0 }, Coord { x: 20.0, y: 20.0 }).to_polygon();
+
+        let points = |poly: &Polygon<f64>| {
+            hilbert_curve_fill_polygon(poly, &settings, MoveType::TopSolidInfill)
+                .into_iter()
+                .flat_map(|chain| chain.moves.into_iter().map(|m| m.end))
+                .collect::<Vec<_>>()
+        };
+
+        let whole = points(&square);
+        let part_fill_area = partial_fill_area(&part, &settings)
+            .bounding_rect()
+            .expect("Fill area is not empty");
+
+        //Corners inside the smaller region lie on the curve filling the larger one
+        let inner: Vec<Coord<f64>> = points(&part)
+            .into_iter()
+            .filter(|p| {
+                part_fill_area.min().x + 1e-6 < p.x
+                    && p.x < part_fill_area.max().x - 1e-6
+                    && part_fill_area.min().y + 1e-6 < p.y
+                    && p.y < part_fill_area.max().y - 1e-6
+            })
+            .collect();
+
+        assert!(!inner.is_empty());
+        for p in inner {
+            assert!(whole
+                .iter()
+                .any(|w| (w.x - p.x).abs() < 1e-6 && (w.y - p.y).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn curves_fill_the_polygon() {
+        let settings = Settings::default().get_layer_settings(0, 0.2);
+        let square = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 30.0, y: 30.0 }).to_polygon();
+        let fill_area = partial_fill_area(&square, &settings);
+        let area = fill_area.unsigned_area();
+
+        let fills = [
+            hilbert_curve_fill_polygon(&square, &settings, MoveType::TopSolidInfill),
+            archimedean_chords_fill_polygon(&square, &settings, MoveType::TopSolidInfill),
+        ];
+
+        for fill in fills {
+            let filled: f64 = fill
+                .iter()
+                .flat_map(|chain| {
+                    chain
+                        .moves
+                        .iter()
+                        .scan(chain.start_point, |start, m| {
+                            let length = Line::new(*start, m.end).euclidean_length();
+                            *start = m.end;
+                            Some(length * m.width)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .sum();
+
+            assert!((filled / area - 1.0).abs() < 0.1, "ratio {}", filled / area);
+
+            let bounds = fill_area.bounding_rect().expect("Fill area is not empty");
+            for chain in &fill {
+                for m in &chain.moves {
+                    assert!(bounds.min().x - 1e-6 <= m.end.x && m.end.x <= bounds.max().x + 1e-6);
+                    assert!(bounds.min().y - 1e-6 <= m.end.y && m.end.y <= bounds.max().y + 1e-6);
+                }
+            }
+        }
+    }
+}
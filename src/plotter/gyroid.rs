@@ -0,0 +1,214 @@
+use crate::plotter::infill::{clipped_line_chains, partial_fill_area};
+use geo::prelude::*;
+use geo::*;
+use gladius_shared::settings::LayerSettings;
+use gladius_shared::types::{MoveChain, MoveType};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+///Average length of the gyroid walls in a unit area of a layer when the period is one
+const GYROID_WALL_LENGTH: f64 = 2.4;
+
+///Number of samples per period used to trace the gyroid walls
+const GYROID_SAMPLES_PER_PERIOD: f64 = 16.0;
+
+///Fill the polygon with the cross section of a gyroid at the given height.
+///Each wall is a connected chain and the walls shift smoothly between layers
+pub fn gyroid_fill_polygon(
+    poly: &Polygon<f64>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    fill_ratio: f64,
+    height: f64,
+) -> Vec<MoveChain> {
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    //Period that gives the requested ratio of filled area
+    let period = GYROID_WALL_LENGTH * width / fill_ratio;
+    let scale = 2.0 * PI / period;
+
+    let fill_area = partial_fill_area(poly, settings);
+
+    let Some(bounds) = fill_area.bounding_rect() else {
+        return vec![];
+    };
+
+    let (sin_z, cos_z) = (scale * height).sin_cos();
+
+    let walls = contour_lines(bounds, period / GYROID_SAMPLES_PER_PERIOD, |x, y| {
+        let (sin_x, cos_x) = (scale * x).sin_cos();
+        let (sin_y, cos_y) = (scale * y).sin_cos();
+
+        sin_x * cos_y + sin_y * cos_z + sin_z * cos_x
+    });
+
+    clipped_line_chains(&fill_area, walls, settings, fill_type)
+}
+
+///Trace the lines where the function is zero with marching squares, joining the
+///segments of neighbouring cells into continuous lines
+fn contour_lines(
+    bounds: Rect<f64>,
+    step: f64,
+    function: impl Fn(f64, f64) -> f64,
+) -> Vec<LineString<f64>> {
+    let min = bounds.min() - Coord { x: step, y: step };
+    let columns = (bounds.width() / step).ceil() as usize + 3;
+    let rows = (bounds.height() / step).ceil() as usize + 3;
+
+    let values: Vec<f64> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            let value = function(min.x + column as f64 * step, min.y + row as f64 * step);
+
+            //Keeps the zero crossings off the grid points
+            if value == 0.0 {
+                f64::EPSILON
+            } else {
+                value
+            }
+        })
+        .collect();
+
+    let value = |column: usize, row: usize| values[row * columns + column];
+
+    //Edges are identified by their lower left grid point and whether they are vertical
+    let horizontal = |column: usize, row: usize| (row * columns + column, false);
+    let vertical = |column: usize, row: usize| (row * columns + column, true);
+
+    let ends = |(index, is_vertical): (usize, bool)| {
+        let (column, row) = (index % columns, index / columns);
+        if is_vertical {
+            ((column, row), (column, row + 1))
+        } else {
+            ((column, row), (column + 1, row))
+        }
+    };
+
+    let crosses = |edge: (usize, bool)| {
+        let ((column, row), (end_column, end_row)) = ends(edge);
+
+        (value(column, row) > 0.0) != (value(end_column, end_row) > 0.0)
+    };
+
+    let mut connections: BTreeMap<(usize, bool), Vec<(usize, bool)>> = BTreeMap::new();
+    let mut connect = |a: (usize, bool), b: (usize, bool)| {
+        connections.entry(a).or_default().push(b);
+        connections.entry(b).or_default().push(a);
+    };
+
+    for row in 0..rows - 1 {
+        for column in 0..columns - 1 {
+            let bottom = horizontal(column, row);
+            let right = vertical(column + 1, row);
+            let top = horizontal(column, row + 1);
+            let left = vertical(column, row);
+
+            let crossed: Vec<(usize, bool)> = [bottom, right, top, left]
+                .iter()
+                .copied()
+                .filter(|edge| crosses(*edge))
+                .collect();
+
+            match crossed.as_slice() {
+                [a, b] => connect(*a, *b),
+                [_, _, _, _] => {
+                    //Saddle, the center decides which corners are joined
+                    let center = (value(column, row)
+                        + value(column + 1, row)
+                        + value(column + 1, row + 1)
+                        + value(column, row + 1))
+                        / 4.0;
+
+                    if (center > 0.0) == (value(column, row) > 0.0) {
+                        connect(bottom, right);
+                        connect(top, left);
+                    } else {
+                        connect(left, bottom);
+                        connect(right, top);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let point = |edge: (usize, bool)| {
+        let ((column, row), (end_column, end_row)) = ends(edge);
+
+        let start_value = value(column, row);
+        let fraction = start_value / (start_value - value(end_column, end_row));
+
+        Coord {
+            x: min.x + (column as f64 + fraction * (end_column - column) as f64) * step,
+            y: min.y + (row as f64 + fraction * (end_row - row) as f64) * step,
+        }
+    };
+
+    //Start with the open lines so they are not split, then the loops
+    let starts: Vec<(usize, bool)> = connections
+        .iter()
+        .filter(|(_, next)| next.len() == 1)
+        .chain(connections.iter().filter(|(_, next)| next.len() != 1))
+        .map(|(edge, _)| *edge)
+        .collect();
+
+    let mut lines = vec![];
+
+    for start in starts {
+        let mut current = start;
+        let mut line = vec![point(start)];
+
+        while let Some(next) = connections.get_mut(&current).and_then(|next| next.pop()) {
+            if let Some(back) = connections.get_mut(&next) {
+                if let Some(position) = back.iter().position(|edge| *edge == current) {
+                    back.swap_remove(position);
+                }
+            }
+
+            line.push(point(next));
+            current = next;
+        }
+
+        if line.len() > 1 {
+            lines.push(LineString::from(line));
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contour_of_circle_is_one_loop() {
+        let lines = contour_lines(
+            Rect::new(Coord { x: -2.0, y: -2.0 }, Coord { x: 2.0, y: 2.0 }),
+            0.1,
+            |x, y| x * x + y * y - 1.0,
+        );
+
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].is_closed());
+
+        let length = lines[0].euclidean_length();
+        assert!((length - 2.0 * PI).abs() < 0.05);
+    }
+
+    #[test]
+    fn lines_crossing_bounds_stay_open() {
+        let lines = contour_lines(
+            Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 1.0, y: 1.0 }),
+            0.1,
+            |x, _y| x - 0.55,
+        );
+
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].is_closed());
+        assert!(lines[0].coords().all(|coord| (coord.x - 0.55).abs() < 1e-9));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::plotter::curve_fill::{archimedean_chords_fill_polygon, hilbert_curve_fill_polygon};
+use crate::plotter::gyroid::gyroid_fill_polygon;
 use crate::plotter::monotone::get_monotone_sections;
 use gladius_shared::settings::LayerSettings;
 use gladius_shared::types::{Move, MoveChain, MoveType, PartialInfillTypes, SolidInfillTypes};
@@ -100,6 +102,250 @@ pub fn support_linear_fill_polygon(
     new_moves
 }
 
+///The area filled by infill, overlapping the inner perimeter by the infill perimeter overlap
+pub fn partial_fill_area(
+    area: &impl PolygonOperations,
+    settings: &LayerSettings,
+) -> MultiPolygon<f64> {
+    area.offset_from(
+        ((-settings.extrusion_width.interior_inner_perimeter / 2.0)
+            * (1.0 - settings.infill_perimeter_overlap_percentage))
+            + (settings.extrusion_width.interior_inner_perimeter / 2.0),
+    )
+}
+
+///Clip the lines to the area, each part inside the area becomes a chain
+pub fn clipped_line_chains(
+    area: &MultiPolygon<f64>,
+    lines: Vec<LineString<f64>>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+) -> Vec<MoveChain> {
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    area.intersection_with_lines(&MultiLineString(lines))
+        .into_iter()
+        .filter_map(|line| {
+            let mut points = line.into_inner().into_iter();
+            let start_point = points.next()?;
+
+            Some(MoveChain {
+                start_point,
+                moves: points
+                    .map(|end| Move {
+                        end,
+                        move_type: fill_type,
+                        width,
+                    })
+                    .collect(),
+                is_loop: false,
+            })
+        })
+        .filter(|chain| !chain.moves.is_empty())
+        .collect()
+}
+
+///Fill the polygon with hexagonal cells that have vertical walls.
+///Each chain zig-zags between two neighbouring columns of walls so the vertical walls are printed by both chains
+pub fn honeycomb_fill_polygon(
+    poly: &Polygon<f64>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    fill_ratio: f64,
+) -> Vec<MoveChain> {
+    let fill_area = partial_fill_area(poly, settings);
+
+    let Some(bounds) = fill_area.bounding_rect() else {
+        return vec![];
+    };
+
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    //Each zig-zag has 4 sides of length side in an area of 3 sides by the column spacing
+    let side = 8.0 * width / (3.0_f64.sqrt() * 3.0 * fill_ratio);
+    let column_spacing = side * 3.0_f64.sqrt() / 2.0;
+    let period = 3.0 * side;
+
+    //Anchored to the origin so the walls line up between layers
+    let first_column = (bounds.min().x / column_spacing).floor() as i64 - 1;
+    let last_column = (bounds.max().x / column_spacing).ceil() as i64 + 1;
+    let start_y = (bounds.min().y / period).floor() * period;
+    let periods = ((bounds.max().y - start_y) / period).ceil() as usize + 1;
+
+    let lines = (first_column..last_column)
+        .map(|column| {
+            let left = column as f64 * column_spacing;
+            let right = left + column_spacing;
+
+            //Walls in even columns start at the bottom of each period
+            let (near, far) = if column.rem_euclid(2) == 0 {
+                (left, right)
+            } else {
+                (right, left)
+            };
+
+            let mut points: Vec<Coord<f64>> = (0..periods)
+                .flat_map(|index| {
+                    let y = start_y + index as f64 * period;
+                    [
+                        Coord { x: near, y },
+                        Coord {
+                            x: near,
+                            y: y + side,
+                        },
+                        Coord {
+                            x: far,
+                            y: y + 1.5 * side,
+                        },
+                        Coord {
+                            x: far,
+                            y: y + 2.5 * side,
+                        },
+                    ]
+                })
+                .collect();
+
+            //Alternate directions so the end of one chain is near the start of the next
+            if column.rem_euclid(2) == 1 {
+                points.reverse();
+            }
+
+            LineString::from(points)
+        })
+        .collect();
+
+    clipped_line_chains(&fill_area, lines, settings, fill_type)
+}
+
+///Fill the polygon with the cross section of stacked truncated octahedrons at the given height.
+///Straight lines bend into octagons and then diamonds before straightening again,
+///switching between lines along y and along x every cell height
+pub fn honeycomb_3d_fill_polygon(
+    poly: &Polygon<f64>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    fill_ratio: f64,
+    height: f64,
+) -> Vec<MoveChain> {
+    let fill_area = partial_fill_area(poly, settings);
+
+    let Some(bounds) = fill_area.bounding_rect() else {
+        return vec![];
+    };
+
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    //The bends lengthen the lines by a factor of (1 + 2(sqrt(2) - 1) offset), an offset of 1/4 on average
+    let cell = width / fill_ratio * (1.0 + (std::f64::consts::SQRT_2 - 1.0) / 2.0);
+
+    //Triangle wave between -1/2 and 1/2 of the cell size, the walls slope at 45 degrees
+    let phase = (height / cell).rem_euclid(2.0);
+    let offset = (phase - 1.0).abs() - 0.5;
+    let along_y = phase < 1.0;
+
+    //Lines along x are generated along y with the axes swapped
+    let swap = |coord: Coord<f64>| {
+        if along_y {
+            coord
+        } else {
+            Coord {
+                x: coord.y,
+                y: coord.x,
+            }
+        }
+    };
+    let (min, max) = (swap(bounds.min()), swap(bounds.max()));
+
+    let first_line = (min.x / cell).floor() as i64 - 1;
+    let last_line = (max.x / cell).ceil() as i64 + 1;
+    let first_cell = (min.y / cell).floor() as i64 - 1;
+    let last_cell = (max.y / cell).ceil() as i64 + 1;
+
+    let lines = (first_line..=last_line)
+        .map(|line| {
+            let mut points = vec![];
+
+            for cell_index in first_cell..last_cell {
+                //Neighbouring lines bend in opposite directions to form the octagons
+                let side = if (line + cell_index).rem_euclid(2) == 0 {
+                    1.0
+                } else {
+                    -1.0
+                };
+                let x = (line as f64 + side * offset) * cell;
+                let bottom = (cell_index as f64 + offset.abs()) * cell;
+                let top = (cell_index as f64 + 1.0 - offset.abs()) * cell;
+
+                points.push(swap(Coord { x, y: bottom }));
+                if top - bottom > f64::EPSILON {
+                    points.push(swap(Coord { x, y: top }));
+                }
+            }
+
+            //Alternate directions so the end of one chain is near the start of the next
+            if line.rem_euclid(2) == 1 {
+                points.reverse();
+            }
+
+            LineString::from(points)
+        })
+        .collect();
+
+    clipped_line_chains(&fill_area, lines, settings, fill_type)
+}
+
+///Fill the polygon with loops that follow its outline, each inset by the spacing from the last
+pub fn concentric_fill_polygon(
+    poly: &Polygon<f64>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    spacing: f64,
+) -> Vec<MoveChain> {
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    let mut chains = vec![];
+    let mut area = partial_fill_area(poly, settings).offset_from(-spacing / 2.0);
+
+    while !area.0.is_empty() {
+        for polygon in &area {
+            let polygon = polygon.simplify(&0.01);
+
+            for ring in std::iter::once(polygon.exterior()).chain(polygon.interiors()) {
+                let mut points = ring.0.iter();
+
+                let Some(start_point) = points.next() else {
+                    continue;
+                };
+
+                //The ring is closed so the last move returns to the start
+                chains.push(MoveChain {
+                    start_point: *start_point,
+                    moves: points
+                        .map(|end| Move {
+                            end: *end,
+                            move_type: fill_type,
+                            width,
+                        })
+                        .collect(),
+                    is_loop: true,
+                });
+            }
+        }
+
+        area = area.offset_from(-spacing);
+    }
+
+    chains
+}
+
 pub fn solid_infill_polygon(
     poly: &Polygon<f64>,
     settings: &LayerSettings,
@@ -120,6 +366,21 @@ pub fn solid_infill_polygon(
 
             linear_fill_polygon(poly, settings, fill_type, angle)
         }
+
+        SolidInfillTypes::Concentric => concentric_fill_polygon(
+            poly,
+            settings,
+            fill_type,
+            settings
+                .extrusion_width
+                .get_value_for_movement_type(&fill_type),
+        ),
+
+        SolidInfillTypes::HilbertCurve => hilbert_curve_fill_polygon(poly, settings, fill_type),
+
+        SolidInfillTypes::ArchimedeanChords => {
+            archimedean_chords_fill_polygon(poly, settings, fill_type)
+        }
     }
 }
 
@@ -188,39 +449,65 @@ pub fn partial_infill_polygon(
             ));
             fill
         }
-        PartialInfillTypes::Cubic => {
-            let mut fill = partial_linear_fill_polygon(
-                poly,
-                settings,
-                MoveType::Infill,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0,
-                layer_height / std::f64::consts::SQRT_2,
-            );
-            fill.append(&mut partial_linear_fill_polygon(
-                poly,
-                settings,
-                MoveType::Infill,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0 + 120.0,
-                layer_height / std::f64::consts::SQRT_2,
-            ));
-            fill.append(&mut partial_linear_fill_polygon(
-                poly,
-                settings,
-                MoveType::Infill,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0 + 240.0,
-                layer_height / std::f64::consts::SQRT_2,
-            ));
-            fill
+        PartialInfillTypes::Cubic | PartialInfillTypes::AdaptiveCubic => {
+            cubic_fill_polygon(poly, settings, fill_ratio, layer_height)
+        }
+        PartialInfillTypes::Honeycomb => {
+            honeycomb_fill_polygon(poly, settings, MoveType::Infill, fill_ratio)
+        }
+        PartialInfillTypes::Honeycomb3D => {
+            honeycomb_3d_fill_polygon(poly, settings, MoveType::Infill, fill_ratio, layer_height)
         }
+        PartialInfillTypes::Gyroid => {
+            gyroid_fill_polygon(poly, settings, MoveType::Infill, fill_ratio, layer_height)
+        }
+        PartialInfillTypes::Concentric => concentric_fill_polygon(
+            poly,
+            settings,
+            MoveType::Infill,
+            settings.extrusion_width.infill / fill_ratio,
+        ),
         PartialInfillTypes::Lightning => {
             unreachable!()
         }
     }
 }
 
+///Angles of the 3 directions of cubic infill lines
+pub const CUBIC_ANGLES: [f64; 3] = [45.0, 45.0 + 120.0, 45.0 + 240.0];
+
+///Spacing between cubic infill lines of the same direction
+pub fn cubic_line_spacing(settings: &LayerSettings, fill_ratio: f64) -> f64 {
+    3.0 * settings.extrusion_width.infill / fill_ratio
+}
+
+///Shift of the cubic infill lines at the height, so the lines form cubes standing on a corner
+pub fn cubic_line_offset(height: f64) -> f64 {
+    height / std::f64::consts::SQRT_2
+}
+
+///Lines in 3 directions that shift with the height to form cubes standing on a corner
+pub fn cubic_fill_polygon(
+    poly: &Polygon<f64>,
+    settings: &LayerSettings,
+    fill_ratio: f64,
+    height: f64,
+) -> Vec<MoveChain> {
+    CUBIC_ANGLES
+        .iter()
+        .flat_map(|angle| {
+            partial_linear_fill_polygon(
+                poly,
+                settings,
+                MoveType::Infill,
+                cubic_line_spacing(settings, fill_ratio),
+                *angle,
+                cubic_line_offset(height),
+            )
+        })
+        .collect()
+}
+
 pub fn spaced_fill_polygon(
     poly: &Polygon<f64>,
     settings: &LayerSettings,
@@ -368,3 +655,82 @@ pub fn spaced_fill_polygon(
         .into_iter()
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gladius_shared::settings::Settings;
+
+    fn filled_ratio(chains: &[MoveChain], area: f64) -> f64 {
+        chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .moves
+                    .iter()
+                    .scan(chain.start_point, |start, m| {
+                        let length = Line::new(*start, m.end).euclidean_length();
+                        *start = m.end;
+                        Some(length * m.width)
+                    })
+                    .sum::<f64>()
+            })
+            .sum::<f64>()
+            / area
+    }
+
+    #[test]
+    fn honeycomb_density_follows_infill_percentage() {
+        let settings = Settings::default().get_layer_settings(0, 0.2);
+        let square = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 60.0, y: 60.0 }).to_polygon();
+        let area = partial_fill_area(&square, &settings).unsigned_area();
+
+        for height in [0.2, 1.0, 1.7] {
+            let fills = [
+                honeycomb_fill_polygon(&square, &settings, MoveType::Infill, 0.2),
+                honeycomb_3d_fill_polygon(&square, &settings, MoveType::Infill, 0.2, height),
+            ];
+
+            for fill in fills {
+                let ratio = filled_ratio(&fill, area);
+                assert!((ratio - 0.2).abs() < 0.04, "ratio {}", ratio);
+            }
+        }
+    }
+
+    #[test]
+    fn concentric_fill_is_closed_loops() {
+        let mut settings = Settings::default().get_layer_settings(0, 0.2);
+        settings.solid_infill_type = SolidInfillTypes::Concentric;
+        let square = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 60.0, y: 60.0 }).to_polygon();
+        let area = partial_fill_area(&square, &settings).unsigned_area();
+
+        let fills = [
+            (
+                solid_infill_polygon(&square, &settings, MoveType::SolidInfill, 0, 0.2),
+                1.0,
+            ),
+            (
+                concentric_fill_polygon(
+                    &square,
+                    &settings,
+                    MoveType::Infill,
+                    settings.extrusion_width.infill / 0.2,
+                ),
+                0.2,
+            ),
+        ];
+
+        for (fill, fill_ratio) in fills {
+            assert!(!fill.is_empty());
+
+            for chain in &fill {
+                assert!(chain.is_loop);
+                assert_eq!(chain.moves.last().map(|m| m.end), Some(chain.start_point));
+            }
+
+            let ratio = filled_ratio(&fill, area);
+            assert!((ratio - fill_ratio).abs() < 0.05, "ratio {}", ratio);
+        }
+    }
+}
@@ -1,3 +1,6 @@
+pub(crate) mod adaptive_cubic;
+mod curve_fill;
+mod gyroid;
 mod infill;
 pub(crate) mod lightning_infill;
 mod monotone;
@@ -8,7 +11,7 @@ pub(crate) mod support;
 pub use crate::plotter::infill::*;
 use crate::plotter::perimeter::*;
 use crate::plotter::polygon_operations::PolygonOperations;
-use crate::utils::point_lerp;
+use crate::utils::{lerp, point_lerp};
 use crate::{Object, Settings, StateChange};
 use coordinate_position::CoordPos;
 use geo::prelude::*;
@@ -21,6 +24,7 @@ use ordered_float::OrderedFloat;
 
 pub trait Plotter {
     fn slice_perimeters_into_chains(&mut self, number_of_perimeters: usize);
+    fn slice_spiral_perimeter_into_chains(&mut self);
     fn shrink_layer(&mut self);
     fn fill_remaining_area(&mut self, solid: bool, layer_count: usize);
     fn fill_solid_subtracted_area(&mut self, other: &MultiPolygon<f64>, layer_count: usize);
@@ -35,6 +39,7 @@ pub trait Plotter {
     fn generate_brim(&mut self, entire_first_layer: MultiPolygon<f64>, brim_width: f64);
     fn order_chains(&mut self);
     fn slice_into_commands(&mut self, commands: &mut Vec<Command>, layer_thickness: f64);
+    fn slice_into_spiral_commands(&mut self, commands: &mut Vec<Command>, layer_thickness: f64);
 }
 
 impl Plotter for Slice {
@@ -72,6 +77,15 @@ impl Plotter for Slice {
         self.remaining_area = self.remaining_area.offset_from(-perimeter_inset);
     }
 
+    fn slice_spiral_perimeter_into_chains(&mut self) {
+        if let Some(chain) = spiral_perimeter(&self.remaining_area, &self.layer_settings) {
+            self.chains.push(chain);
+        }
+
+        //Nothing is printed inside a spiral layer
+        self.remaining_area = MultiPolygon(vec![]);
+    }
+
     fn shrink_layer(&mut self) {
         if let Some(shrink_ammount) = self.layer_settings.layer_shrink_amount {
             self.support_tower = self
@@ -174,10 +188,13 @@ impl Plotter for Slice {
             .intersection_with(&self.remaining_area);
 
         for poly in &solid_area {
-            let angle = 45.0 + (120_f64) * layer_count as f64;
-
-            let new_moves =
-                linear_fill_polygon(poly, &self.layer_settings, MoveType::TopSolidInfill, angle);
+            let new_moves = solid_infill_polygon(
+                poly,
+                &self.layer_settings,
+                MoveType::TopSolidInfill,
+                layer_count,
+                self.get_height(),
+            );
 
             for chain in new_moves {
                 self.chains.push(chain);
@@ -428,6 +445,7 @@ impl Plotter for Slice {
 
                 commands.push(Command::MoveTo {
                     end: chain.start_point,
+                    z: None,
                 });
                 commands.append(&mut chain.create_commands(&self.layer_settings, layer_thickness));
 
@@ -435,6 +453,48 @@ impl Plotter for Slice {
             }
         }
     }
+
+    fn slice_into_spiral_commands(&mut self, commands: &mut Vec<Command>, layer_thickness: f64) {
+        //Spiral layers only contain the spiral perimeter in chains
+        let spiral_chains = std::mem::take(&mut self.chains);
+
+        //Skirts and supports are printed at the height the previous spiral finished at
+        self.slice_into_commands(commands, layer_thickness);
+
+        for chain in spiral_chains {
+            commands.push(Command::MoveTo {
+                end: chain.start_point,
+                z: None,
+            });
+
+            let mut spiral_commands = chain.create_commands(&self.layer_settings, layer_thickness);
+
+            let total_length: f64 = spiral_commands
+                .iter()
+                .map(|cmd| match cmd {
+                    Command::MoveAndExtrude { start, end, .. } => start.euclidean_distance(end),
+                    _ => 0.0,
+                })
+                .sum();
+
+            //Raise z along the loop from the bottom to the top of the layer
+            let mut length = 0.0;
+            for cmd in &mut spiral_commands {
+                if let Command::MoveAndExtrude { start, end, z, .. } = cmd {
+                    length += start.euclidean_distance(end);
+                    if total_length > 0.0 {
+                        *z = Some(lerp(
+                            self.bottom_height,
+                            self.top_height,
+                            length / total_length,
+                        ));
+                    }
+                }
+            }
+
+            commands.append(&mut spiral_commands);
+        }
+    }
 }
 
 fn get_optimal_bridge_angle(fill_area: &Polygon<f64>, unsupported_area: &MultiPolygon<f64>) -> f64 {
@@ -504,6 +564,9 @@ pub fn convert_objects_into_moves(objects: Vec<Object>, settings: &Settings) ->
         .enumerate()
         .map(|(object_num, object)| {
             let mut last_layer = 0.0;
+            let name = object.info.name;
+            let object_settings = object.settings;
+            let settings = object_settings.as_ref().unwrap_or(settings);
 
             object
                 .layers
@@ -512,11 +575,19 @@ pub fn convert_objects_into_moves(objects: Vec<Object>, settings: &Settings) ->
                 .map(|(layer_num, mut slice)| {
                     let layer_settings = settings.get_layer_settings(layer_num, slice.top_height);
                     let mut moves = vec![];
-                    moves.push(Command::ChangeObject { object: object_num });
-                    moves.push(Command::LayerChange {
-                        z: slice.top_height,
-                        index: layer_num,
+                    moves.push(Command::ChangeObject {
+                        object: object_num,
+                        name: name.clone(),
                     });
+                    let spiral = settings.is_spiral_layer(layer_num);
+
+                    //Spiral layers raise z while extruding instead of changing layers
+                    if !spiral {
+                        moves.push(Command::LayerChange {
+                            z: slice.top_height,
+                            index: layer_num,
+                        });
+                    }
                     moves.push(Command::SetState {
                         new_state: StateChange {
                             extruder_temp: Some(layer_settings.extruder_temp),
@@ -531,7 +602,11 @@ pub fn convert_objects_into_moves(objects: Vec<Object>, settings: &Settings) ->
                             retract: RetractionType::NoRetract,
                         },
                     });
-                    slice.slice_into_commands(&mut moves, slice.top_height - last_layer);
+                    if spiral {
+                        slice.slice_into_spiral_commands(&mut moves, slice.top_height - last_layer);
+                    } else {
+                        slice.slice_into_commands(&mut moves, slice.top_height - last_layer);
+                    }
 
                     last_layer = slice.top_height;
                     (slice.top_height, moves)
@@ -549,3 +624,123 @@ pub fn convert_objects_into_moves(objects: Vec<Object>, settings: &Settings) ->
         .flat_map(|(_, layer_moves)| layer_moves)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::SliceCache;
+    use crate::generate_commands;
+    use crate::repair::Model;
+    use crate::utils::{CancelToken, MessageSink};
+    use gladius_shared::loader::lowest_vertex_first;
+    use gladius_shared::types::{IndexedTriangle, ObjectInfo, Vertex};
+
+    ///A closed cylinder standing on the bed at the center of the build area
+    fn cylinder(settings: &Settings, radius: f64, height: f64) -> Model {
+        let segments = 64;
+        let center_x = settings.print_x / 2.0;
+        let center_y = settings.print_y / 2.0;
+
+        let mut vertices = vec![
+            Vertex {
+                x: center_x,
+                y: center_y,
+                z: 0.0,
+            },
+            Vertex {
+                x: center_x,
+                y: center_y,
+                z: height,
+            },
+        ];
+        for index in 0..segments {
+            let (sin, cos) =
+                (index as f64 * 2.0 * std::f64::consts::PI / segments as f64).sin_cos();
+            for z in [0.0, height] {
+                vertices.push(Vertex {
+                    x: center_x + radius * cos,
+                    y: center_y + radius * sin,
+                    z,
+                });
+            }
+        }
+
+        let mut triangles = vec![];
+        for index in 0..segments {
+            let bottom = 2 + 2 * index;
+            let top = bottom + 1;
+            let next_bottom = 2 + 2 * ((index + 1) % segments);
+            let next_top = next_bottom + 1;
+
+            triangles.push(IndexedTriangle {
+                verts: [0, next_bottom, bottom],
+            });
+            triangles.push(IndexedTriangle {
+                verts: [1, top, next_top],
+            });
+            triangles.push(IndexedTriangle {
+                verts: [bottom, next_bottom, next_top],
+            });
+            triangles.push(IndexedTriangle {
+                verts: [bottom, next_top, top],
+            });
+        }
+
+        let triangles = triangles
+            .into_iter()
+            .map(|triangle| lowest_vertex_first(triangle, &vertices))
+            .collect();
+
+        (vertices, triangles)
+    }
+
+    #[test]
+    fn spiral_vase_raises_z_to_the_top() {
+        let settings = Settings {
+            spiral_vase: true,
+            layer_height: 0.2,
+            ..Default::default()
+        };
+        //The first layer is 0.3mm so the layers are 0.3 + 0.2 * n
+        let height = 5.1;
+        let model = cylinder(&settings, 10.0, height);
+
+        let commands = generate_commands(
+            &[model],
+            &[ObjectInfo::default()],
+            &settings,
+            &mut SliceCache::default(),
+            &CancelToken::default(),
+            MessageSink::default(),
+        )
+        .expect("Cylinder slices");
+
+        //Only the bottom layers change layer normally
+        let layer_changes: Vec<usize> = commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::LayerChange { index, .. } => Some(*index),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            layer_changes,
+            (0..settings.bottom_layers).collect::<Vec<_>>()
+        );
+
+        let spiral_heights: Vec<f64> = commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::MoveAndExtrude { z, .. } => *z,
+                _ => None,
+            })
+            .collect();
+
+        assert!(!spiral_heights.is_empty());
+        assert!(spiral_heights
+            .iter()
+            .zip(spiral_heights.iter().skip(1))
+            .all(|(a, b)| a <= b));
+        assert!((spiral_heights.last().expect("Not empty") - height).abs() < 0.000001);
+    }
+}
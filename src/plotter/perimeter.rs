@@ -6,6 +6,7 @@ use geo::*;
 
 use crate::PolygonOperations;
 use itertools::Itertools;
+use ordered_float::OrderedFloat;
 
 pub fn inset_polygon_recursive(
     poly: &MultiPolygon<f64>,
@@ -127,3 +128,49 @@ pub fn inset_polygon_recursive(
             }
         })
 }
+
+pub fn spiral_perimeter(poly: &MultiPolygon<f64>, settings: &LayerSettings) -> Option<MoveChain> {
+    let move_type = MoveType::ExteriorSurfacePerimeter;
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&move_type);
+
+    //Only the largest outline can be printed as the spiral never travels between islands
+    let polygon = poly
+        .offset_from(width / -2.0)
+        .into_iter()
+        .max_by(|a, b| {
+            a.unsigned_area()
+                .partial_cmp(&b.unsigned_area())
+                .expect("Area should not be NAN")
+        })?
+        .simplify(&0.01);
+
+    //The exterior is closed so the last point repeats the first
+    let ring = &polygon.exterior().0;
+    if ring.len() < 4 {
+        return None;
+    }
+    let points = &ring[..ring.len() - 1];
+
+    //Start nearest the origin so the seam stays in the same place on every layer
+    let start = points
+        .iter()
+        .position_min_by_key(|c| OrderedFloat(c.x * c.x + c.y * c.y))?;
+
+    let moves = points[start + 1..]
+        .iter()
+        .chain(points[..=start].iter())
+        .map(|&end| Move {
+            end,
+            move_type,
+            width,
+        })
+        .collect();
+
+    Some(MoveChain {
+        start_point: points[start],
+        moves,
+        is_loop: true,
+    })
+}
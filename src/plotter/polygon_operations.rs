@@ -14,6 +14,9 @@ pub trait PolygonOperations {
     fn union_with(&self, other: &MultiPolygon<f64>) -> MultiPolygon<f64>;
 
     fn xor_with(&self, other: &MultiPolygon<f64>) -> MultiPolygon<f64>;
+
+    ///The parts of the lines inside the polygon
+    fn intersection_with_lines(&self, lines: &MultiLineString<f64>) -> MultiLineString<f64>;
 }
 
 impl PolygonOperations for MultiPolygon<f64> {
@@ -42,6 +45,10 @@ impl PolygonOperations for MultiPolygon<f64> {
     fn xor_with(&self, other: &MultiPolygon<f64>) -> MultiPolygon<f64> {
         geo_clipper::Clipper::xor(self, other, 1000000.0)
     }
+
+    fn intersection_with_lines(&self, lines: &MultiLineString<f64>) -> MultiLineString<f64> {
+        geo_clipper::ClipperOpen::intersection(lines, self, 1000000.0)
+    }
 }
 
 impl PolygonOperations for Polygon<f64> {
@@ -70,4 +77,8 @@ impl PolygonOperations for Polygon<f64> {
     fn xor_with(&self, other: &MultiPolygon<f64>) -> MultiPolygon<f64> {
         geo_clipper::Clipper::xor(self, other, 1000000.0)
     }
+
+    fn intersection_with_lines(&self, lines: &MultiLineString<f64>) -> MultiLineString<f64> {
+        geo_clipper::ClipperOpen::intersection(lines, self, 1000000.0)
+    }
 }
@@ -0,0 +1,476 @@
+use gladius_shared::loader::lowest_vertex_first;
+use gladius_shared::types::{IndexedTriangle, Vertex};
+use gladius_shared::warning::SlicerWarnings;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+///Vertices closer than this distance are merged into a single vertex
+const WELD_DISTANCE: f64 = 0.0001;
+
+///Holes bounded by at most this many edges are closed
+const MAX_HOLE_EDGES: usize = 32;
+
+pub type Model = (Vec<Vertex>, Vec<IndexedTriangle>);
+
+/// Repair each model so it can be turned into a tower.
+///
+/// Returns the repaired models and a warning for every kind of change made to each model.
+pub fn repair_models(models: Vec<Model>) -> (Vec<Model>, Vec<SlicerWarnings>) {
+    let mut warnings = vec![];
+
+    let models = models
+        .into_iter()
+        .enumerate()
+        .map(|(object, (vertices, triangles))| {
+            let (vertices, triangles, welded) = weld_vertices(vertices, triangles);
+            let (triangles, degenerate) = remove_degenerate_triangles(triangles);
+            let (triangles, duplicate) = remove_duplicate_triangles(triangles);
+            let (triangles, flipped) = fix_winding(&vertices, triangles);
+            let (triangles, holes) = close_holes(triangles);
+
+            if welded > 0 {
+                warnings.push(SlicerWarnings::VerticesWelded {
+                    object,
+                    count: welded,
+                });
+            }
+            if degenerate > 0 {
+                warnings.push(SlicerWarnings::DegenerateTrianglesRemoved {
+                    object,
+                    count: degenerate,
+                });
+            }
+            if duplicate > 0 {
+                warnings.push(SlicerWarnings::DuplicateTrianglesRemoved {
+                    object,
+                    count: duplicate,
+                });
+            }
+            if flipped > 0 {
+                warnings.push(SlicerWarnings::TrianglesFlipped {
+                    object,
+                    count: flipped,
+                });
+            }
+            if holes > 0 {
+                warnings.push(SlicerWarnings::HolesClosed {
+                    object,
+                    count: holes,
+                });
+            }
+
+            //Repairs can change the vertex order of triangles so the lowest vertex must be first again
+            let triangles = triangles
+                .into_iter()
+                .map(|triangle| lowest_vertex_first(triangle, &vertices))
+                .collect();
+
+            (vertices, triangles)
+        })
+        .collect();
+
+    (models, warnings)
+}
+
+/// Merge vertices within the weld distance of each other.
+///
+/// Returns the new vertices and triangles and the number of vertices that were moved to merge.
+/// Vertices at the exact same position are merged but not counted.
+fn weld_vertices(
+    vertices: Vec<Vertex>,
+    triangles: Vec<IndexedTriangle>,
+) -> (Vec<Vertex>, Vec<IndexedTriangle>, usize) {
+    let cell = |v: &Vertex| {
+        (
+            (v.x / WELD_DISTANCE).floor() as i64,
+            (v.y / WELD_DISTANCE).floor() as i64,
+            (v.z / WELD_DISTANCE).floor() as i64,
+        )
+    };
+
+    let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+    let mut welded_vertices: Vec<Vertex> = vec![];
+    let mut welded = 0;
+
+    let remap: Vec<usize> = vertices
+        .iter()
+        .map(|vertex| {
+            let (x, y, z) = cell(vertex);
+
+            //Check the neighboring cells as close vertices can be on either side of a cell boundary
+            let existing = (-1..=1)
+                .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (dx, dy, dz))))
+                .filter_map(|(dx, dy, dz)| grid.get(&(x + dx, y + dy, z + dz)))
+                .flatten()
+                .copied()
+                .find(|index| {
+                    let other = &welded_vertices[*index];
+                    (other.x - vertex.x).powi(2)
+                        + (other.y - vertex.y).powi(2)
+                        + (other.z - vertex.z).powi(2)
+                        <= WELD_DISTANCE * WELD_DISTANCE
+                });
+
+            match existing {
+                Some(index) => {
+                    if welded_vertices[index] != *vertex {
+                        welded += 1;
+                    }
+                    index
+                }
+                None => {
+                    let index = welded_vertices.len();
+                    welded_vertices.push(*vertex);
+                    grid.entry((x, y, z)).or_default().push(index);
+                    index
+                }
+            }
+        })
+        .collect();
+
+    let triangles = triangles
+        .into_iter()
+        .map(|triangle| IndexedTriangle {
+            verts: triangle.verts.map(|index| remap[index]),
+        })
+        .collect();
+
+    (welded_vertices, triangles, welded)
+}
+
+/// Remove triangles that use the same vertex more than once.
+fn remove_degenerate_triangles(triangles: Vec<IndexedTriangle>) -> (Vec<IndexedTriangle>, usize) {
+    let count = triangles.len();
+    let triangles: Vec<IndexedTriangle> = triangles
+        .into_iter()
+        .filter(|triangle| {
+            let [a, b, c] = triangle.verts;
+            a != b && b != c && c != a
+        })
+        .collect();
+
+    let removed = count - triangles.len();
+    (triangles, removed)
+}
+
+/// Remove triangles that use the same vertices as an earlier triangle regardless of winding.
+fn remove_duplicate_triangles(triangles: Vec<IndexedTriangle>) -> (Vec<IndexedTriangle>, usize) {
+    let count = triangles.len();
+    let mut seen = HashSet::new();
+    let triangles: Vec<IndexedTriangle> = triangles
+        .into_iter()
+        .filter(|triangle| {
+            let mut key = triangle.verts;
+            key.sort_unstable();
+            seen.insert(key)
+        })
+        .collect();
+
+    let removed = count - triangles.len();
+    (triangles, removed)
+}
+
+/// Make the winding of every connected part consistent and facing outwards.
+///
+/// Returns the triangles and the number of triangles that were flipped.
+fn fix_winding(
+    vertices: &[Vertex],
+    mut triangles: Vec<IndexedTriangle>,
+) -> (Vec<IndexedTriangle>, usize) {
+    let mut edge_map: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (index, triangle) in triangles.iter().enumerate() {
+        for (start, end) in triangle_edges(triangle) {
+            edge_map
+                .entry((start.min(end), start.max(end)))
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let mut flipped = vec![false; triangles.len()];
+    let mut visited = vec![false; triangles.len()];
+
+    for seed in 0..triangles.len() {
+        if visited[seed] {
+            continue;
+        }
+
+        visited[seed] = true;
+        let mut component = vec![seed];
+        let mut queue = VecDeque::from([seed]);
+
+        while let Some(current) = queue.pop_front() {
+            for (start, end) in triangle_edges(&triangles[current]) {
+                //Only propagate across manifold edges
+                let neighbors = &edge_map[&(start.min(end), start.max(end))];
+                if neighbors.len() != 2 {
+                    continue;
+                }
+
+                let neighbor = if neighbors[0] == current {
+                    neighbors[1]
+                } else {
+                    neighbors[0]
+                };
+
+                if visited[neighbor] {
+                    continue;
+                }
+
+                //A consistent neighbor uses the shared edge in the opposite direction
+                if triangle_edges(&triangles[neighbor]).contains(&(start, end)) {
+                    flip(&mut triangles[neighbor]);
+                    flipped[neighbor] = !flipped[neighbor];
+                }
+
+                visited[neighbor] = true;
+                component.push(neighbor);
+                queue.push_back(neighbor);
+            }
+        }
+
+        //Inside out parts have a negative volume
+        if signed_volume(vertices, component.iter().map(|index| &triangles[*index])) < 0.0 {
+            for index in component {
+                flip(&mut triangles[index]);
+                flipped[index] = !flipped[index];
+            }
+        }
+    }
+
+    let count = flipped.into_iter().filter(|flipped| *flipped).count();
+    (triangles, count)
+}
+
+/// Close small holes by filling each boundary loop with a fan of triangles.
+///
+/// Returns the triangles and the number of holes that were closed.
+fn close_holes(mut triangles: Vec<IndexedTriangle>) -> (Vec<IndexedTriangle>, usize) {
+    let edges: HashSet<(usize, usize)> = triangles.iter().flat_map(triangle_edges).collect();
+
+    //Edges without a matching edge in the opposite direction are on the boundary of a hole
+    let mut boundary: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (start, end) in &edges {
+        if !edges.contains(&(*end, *start)) {
+            boundary.entry(*start).or_default().push(*end);
+        }
+    }
+
+    let mut starts: Vec<usize> = boundary.keys().copied().collect();
+    starts.sort_unstable();
+
+    let mut holes = 0;
+
+    for start in starts {
+        let mut hole = vec![start];
+        let mut current = start;
+
+        //Walk the boundary until it loops back to the start
+        let closed = loop {
+            let next = match boundary.get_mut(&current).and_then(|ends| ends.pop()) {
+                Some(next) => next,
+                None => break false,
+            };
+
+            if next == start {
+                break true;
+            }
+
+            hole.push(next);
+            current = next;
+
+            if hole.len() > MAX_HOLE_EDGES {
+                break false;
+            }
+        };
+
+        if closed && hole.len() >= 3 {
+            //The new triangles use the boundary edges in the opposite direction
+            for window in 1..hole.len() - 1 {
+                triangles.push(IndexedTriangle {
+                    verts: [hole[0], hole[window + 1], hole[window]],
+                });
+            }
+            holes += 1;
+        }
+    }
+
+    (triangles, holes)
+}
+
+fn triangle_edges(triangle: &IndexedTriangle) -> [(usize, usize); 3] {
+    let [a, b, c] = triangle.verts;
+    [(a, b), (b, c), (c, a)]
+}
+
+#[inline]
+fn flip(triangle: &mut IndexedTriangle) {
+    triangle.verts.swap(1, 2);
+}
+
+fn signed_volume<'a>(
+    vertices: &[Vertex],
+    triangles: impl Iterator<Item = &'a IndexedTriangle>,
+) -> f64 {
+    triangles
+        .map(|triangle| {
+            let a = vertices[triangle.verts[0]];
+            let b = vertices[triangle.verts[1]];
+            let c = vertices[triangle.verts[2]];
+
+            (a.x * (b.y * c.z - b.z * c.y) - a.y * (b.x * c.z - b.z * c.x)
+                + a.z * (b.x * c.y - b.y * c.x))
+                / 6.0
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> Model {
+        let vertices = (0..8)
+            .map(|index| Vertex {
+                x: (index & 1) as f64,
+                y: ((index >> 1) & 1) as f64,
+                z: ((index >> 2) & 1) as f64,
+            })
+            .collect();
+
+        let triangles = [
+            [0, 2, 1],
+            [1, 2, 3],
+            [4, 5, 6],
+            [5, 7, 6],
+            [0, 1, 4],
+            [1, 5, 4],
+            [2, 6, 3],
+            [3, 6, 7],
+            [0, 4, 2],
+            [2, 4, 6],
+            [1, 3, 5],
+            [3, 7, 5],
+        ]
+        .iter()
+        .map(|verts| IndexedTriangle { verts: *verts })
+        .collect();
+
+        (vertices, triangles)
+    }
+
+    #[test]
+    fn valid_model_unchanged_test() {
+        let (vertices, triangles) = cube();
+        let (models, warnings) = repair_models(vec![(vertices.clone(), triangles.clone())]);
+
+        assert!(warnings.is_empty());
+        assert_eq!(models[0].0, vertices);
+        assert_eq!(models[0].1.len(), triangles.len());
+    }
+
+    #[test]
+    fn weld_and_degenerate_test() {
+        let (mut vertices, mut triangles) = cube();
+
+        //Split the top face off onto near duplicate vertices
+        vertices.push(Vertex {
+            x: 0.0,
+            y: 0.0,
+            z: 1.00001,
+        });
+        triangles[2].verts[0] = 8;
+        //A sliver triangle that collapses when welded
+        triangles.push(IndexedTriangle { verts: [4, 8, 5] });
+
+        let (models, warnings) = repair_models(vec![(vertices, triangles)]);
+
+        assert_eq!(models[0].0.len(), 8);
+        assert_eq!(models[0].1.len(), 12);
+        assert!(warnings.contains(&SlicerWarnings::VerticesWelded {
+            object: 0,
+            count: 1
+        }));
+        assert!(
+            warnings.contains(&SlicerWarnings::DegenerateTrianglesRemoved {
+                object: 0,
+                count: 1
+            })
+        );
+    }
+
+    #[test]
+    fn duplicate_test() {
+        let (vertices, mut triangles) = cube();
+        triangles.push(triangles[3]);
+        triangles.push(triangles[5]);
+
+        let (models, warnings) = repair_models(vec![(vertices, triangles)]);
+
+        assert_eq!(models[0].1.len(), 12);
+        assert_eq!(
+            warnings,
+            vec![SlicerWarnings::DuplicateTrianglesRemoved {
+                object: 0,
+                count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn winding_test() {
+        let (vertices, mut triangles) = cube();
+        flip(&mut triangles[7]);
+
+        let (models, warnings) = repair_models(vec![(vertices.clone(), triangles)]);
+
+        assert_eq!(
+            warnings,
+            vec![SlicerWarnings::TrianglesFlipped {
+                object: 0,
+                count: 1
+            }]
+        );
+        assert!((signed_volume(&vertices, models[0].1.iter()) - 1.0).abs() < 0.00001);
+
+        //Inside out models are flipped completely
+        let (vertices, mut triangles) = cube();
+        triangles.iter_mut().for_each(flip);
+
+        let (models, warnings) = repair_models(vec![(vertices.clone(), triangles)]);
+
+        assert_eq!(
+            warnings,
+            vec![SlicerWarnings::TrianglesFlipped {
+                object: 0,
+                count: 12
+            }]
+        );
+        assert!((signed_volume(&vertices, models[0].1.iter()) - 1.0).abs() < 0.00001);
+    }
+
+    #[test]
+    fn hole_test() {
+        let (vertices, mut triangles) = cube();
+
+        //Remove the top face
+        triangles.drain(2..4);
+
+        let (models, warnings) = repair_models(vec![(vertices.clone(), triangles)]);
+
+        assert_eq!(
+            warnings,
+            vec![SlicerWarnings::HolesClosed {
+                object: 0,
+                count: 1
+            }]
+        );
+        assert_eq!(models[0].1.len(), 12);
+        assert!((signed_volume(&vertices, models[0].1.iter()) - 1.0).abs() < 0.00001);
+
+        //Every triangle must start with its lowest vertex
+        for triangle in &models[0].1 {
+            let [a, b, c] = triangle.verts;
+            assert!(vertices[a] < vertices[b] && vertices[a] < vertices[c]);
+        }
+    }
+}
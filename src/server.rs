@@ -0,0 +1,480 @@
+use crate::bounds_checking::check_model_bounds;
+use crate::cache::SliceCache;
+use crate::calculation::calculate_values;
+use crate::converter::{convert_to_file, convert_to_string};
+use crate::generate_commands;
+use crate::input::{load_models, load_settings};
+use crate::repair::{repair_models, Model};
+use crate::utils::{
+    display_state_update, get_message_format, write_message, CancelToken, MessageFormat,
+    MessageSink,
+};
+use gladius_shared::error::SlicerErrors;
+use gladius_shared::messages::{Message, Request, RequestMessage, ResponseMessage};
+use gladius_shared::settings::{Settings, SettingsValidationResult};
+use gladius_shared::types::ObjectInfo;
+use log::error;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex, PoisonError};
+
+///Serve requests from standard in, or from each connection to the socket in turn, until shut down
+pub fn run_server(socket_path: Option<&str>) -> Result<(), SlicerErrors> {
+    let mut server = Server::default();
+
+    match socket_path {
+        Some(path) => server.listen(path),
+        None => {
+            server.serve(
+                std::io::stdin(),
+                Box::new(BufWriter::new(std::io::stdout())),
+            );
+            Ok(())
+        }
+    }
+}
+
+///The state kept between requests so models are only loaded once and unchanged stages are reused
+#[derive(Default)]
+struct Server {
+    settings: Settings,
+    models: Vec<Model>,
+    infos: Vec<ObjectInfo>,
+    cache: SliceCache,
+}
+
+impl Server {
+    #[cfg(unix)]
+    fn listen(&mut self, path: &str) -> Result<(), SlicerErrors> {
+        let connection_error = || SlicerErrors::ServerConnectionError {
+            address: path.to_string(),
+        };
+
+        let listener =
+            std::os::unix::net::UnixListener::bind(path).map_err(|_| connection_error())?;
+
+        let mut result = Ok(());
+
+        for stream in listener.incoming() {
+            match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+                Ok((reader, writer)) => {
+                    if self.serve(reader, Box::new(BufWriter::new(writer))) {
+                        break;
+                    }
+                }
+                Err(_) => {
+                    result = Err(connection_error());
+                    break;
+                }
+            }
+        }
+
+        //The socket file is left behind by the listener
+        std::fs::remove_file(path).ok();
+
+        result
+    }
+
+    #[cfg(not(unix))]
+    fn listen(&mut self, path: &str) -> Result<(), SlicerErrors> {
+        Err(SlicerErrors::ServerConnectionError {
+            address: path.to_string(),
+        })
+    }
+
+    ///Handle the requests of a single connection, returns true if shutdown was requested
+    fn serve(&mut self, reader: impl Read + Send + 'static, writer: Box<dyn Write + Send>) -> bool {
+        let connection = Connection {
+            writer: Mutex::new(writer),
+            closed: AtomicBool::new(false),
+        };
+
+        //Requests are read on their own thread so cancellations arrive while slicing
+        let cancel_state = Arc::new(Mutex::new(CancelState::default()));
+        let (sender, receiver) = channel();
+
+        let reader_cancel_state = cancel_state.clone();
+        std::thread::spawn(move || {
+            read_requests(BufReader::new(reader), |request| {
+                if let Ok(RequestMessage {
+                    request: Request::Cancel(cancelled_id),
+                    ..
+                }) = &request
+                {
+                    reader_cancel_state
+                        .lock()
+                        .expect("Cancel lock poisoned")
+                        .cancel(*cancelled_id);
+                }
+                sender.send(request).is_ok()
+            })
+        });
+
+        for request in receiver {
+            let shutdown = self.respond(request, &cancel_state, &connection);
+
+            //Nothing more can be sent once writing to the connection fails
+            if shutdown || connection.is_closed() {
+                return shutdown;
+            }
+        }
+
+        false
+    }
+
+    ///Handle a single request and send the responses, returns true if shutdown was requested
+    fn respond(
+        &mut self,
+        request: Result<RequestMessage, SlicerErrors>,
+        cancel_state: &Mutex<CancelState>,
+        connection: &Connection,
+    ) -> bool {
+        let RequestMessage { id, request } = match request {
+            Ok(request) => request,
+            Err(err) => {
+                //The request could not be read so it has no id
+                connection.send(0, Message::Error(err));
+                return false;
+            }
+        };
+
+        let Some(cancel) = cancel_state.lock().expect("Cancel lock poisoned").start(id) else {
+            connection.send(id, Message::Error(SlicerErrors::Cancelled));
+            return false;
+        };
+
+        //The request stops early once its responses can't be sent
+        let send = |message| {
+            if !connection.send(id, message) {
+                cancel.cancel();
+            }
+        };
+        let messages = MessageSink::new(&send);
+
+        let mut shutdown = false;
+
+        match request {
+            Request::Shutdown => shutdown = true,
+            Request::Cancel(cancelled_id) => {
+                //The request has already finished or was skipped
+                cancel_state
+                    .lock()
+                    .expect("Cancel lock poisoned")
+                    .cancelled
+                    .remove(&cancelled_id);
+            }
+            request => {
+                let result = self.handle(request, &cancel, messages);
+                cancel_state.lock().expect("Cancel lock poisoned").running = None;
+
+                if let Err(err) = result {
+                    messages.send(Message::Error(err));
+                    return false;
+                }
+            }
+        }
+
+        cancel_state.lock().expect("Cancel lock poisoned").running = None;
+        display_state_update("Done", messages);
+
+        shutdown
+    }
+
+    fn handle(
+        &mut self,
+        request: Request,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<(), SlicerErrors> {
+        match request {
+            Request::LoadModels {
+                inputs,
+                project_settings,
+                arrange,
+                arrange_rotate,
+            } => {
+                display_state_update("Loading Inputs", messages);
+                let (models, infos, settings, input_warnings) = load_models(
+                    self.settings.clone(),
+                    &inputs,
+                    project_settings,
+                    arrange,
+                    arrange_rotate,
+                    messages,
+                )?;
+
+                for warning in input_warnings {
+                    messages.send(Message::Warning(warning))
+                }
+
+                display_state_update("Repairing Models", messages);
+                let (models, repair_warnings) = repair_models(models);
+
+                for warning in repair_warnings {
+                    messages.send(Message::Warning(warning))
+                }
+
+                self.models = models;
+                self.infos = infos;
+                self.settings = settings;
+            }
+            Request::LoadSettings(filepath) => {
+                self.settings = load_settings(&filepath)?;
+            }
+            Request::SetSettings(partial_settings) => {
+                self.settings = partial_settings.get_settings()?;
+            }
+            Request::Slice(output) => {
+                self.slice(output.as_deref(), cancel, messages)?;
+            }
+            Request::Cancel(_) | Request::Shutdown => {}
+        }
+
+        Ok(())
+    }
+
+    fn slice(
+        &mut self,
+        output: Option<&str>,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<(), SlicerErrors> {
+        if self.models.is_empty() {
+            return Err(SlicerErrors::NoModelsLoaded);
+        }
+
+        check_model_bounds(&self.models, &self.settings)?;
+
+        match self.settings.validate_settings() {
+            SettingsValidationResult::NoIssue => {}
+            SettingsValidationResult::Warning(slicer_warning) => {
+                messages.send(Message::Warning(slicer_warning))
+            }
+            SettingsValidationResult::Error(slicer_error) => return Err(slicer_error),
+        }
+
+        let moves = generate_commands(
+            &self.models,
+            &self.infos,
+            &self.settings,
+            &mut self.cache,
+            cancel,
+            messages,
+        )?;
+
+        messages.send(Message::Commands(moves.clone()));
+
+        display_state_update("Calculate Values", messages);
+        messages.send(Message::CalculatedValues(calculate_values(
+            &moves,
+            &self.settings,
+        )));
+
+        display_state_update("Outputting G-code", messages);
+        if let Some(file_path) = output {
+            convert_to_file(&moves, &self.settings, file_path, cancel, messages)?;
+        } else {
+            messages.send(Message::GCode(convert_to_string(
+                &moves,
+                &self.settings,
+                cancel,
+                messages,
+            )?));
+        }
+
+        Ok(())
+    }
+}
+
+///Read requests until the input ends or the handler returns false, misformatted requests are passed on as errors
+fn read_requests(
+    mut reader: impl BufRead,
+    mut handle: impl FnMut(Result<RequestMessage, SlicerErrors>) -> bool,
+) {
+    match get_message_format() {
+        MessageFormat::Bincode => loop {
+            let request = match bincode::deserialize_from(&mut reader) {
+                Ok(request) => request,
+                //The input has been closed
+                Err(err) if matches!(*err, bincode::ErrorKind::Io(_)) => break,
+                //The rest of the input can't be read once a request is misread
+                Err(_) => {
+                    handle(Err(SlicerErrors::InputMisformat));
+                    break;
+                }
+            };
+
+            if !handle(Ok(request)) {
+                break;
+            }
+        },
+        MessageFormat::Json => {
+            for line in reader.lines().map_while(Result::ok) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let request = serde_json::from_str(&line).map_err(|_| SlicerErrors::InputMisformat);
+
+                if !handle(request) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+///The output of a connection, closed once a response can't be written to it
+struct Connection {
+    writer: Mutex<Box<dyn Write + Send>>,
+    closed: AtomicBool,
+}
+
+impl Connection {
+    ///Write the response to the request, returns false if the connection is closed
+    fn send(&self, id: u64, message: Message) -> bool {
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if self.is_closed() {
+            return false;
+        }
+
+        let response = ResponseMessage { id, message };
+        if let Err(err) = write_message(&mut *writer, &response, get_message_format()) {
+            error!("Could not write to the connection, closing it: {}", err);
+            self.closed.store(true, Ordering::SeqCst);
+            return false;
+        }
+
+        true
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+///The cancelled requests and the request being handled, shared with the thread reading requests
+#[derive(Default)]
+struct CancelState {
+    cancelled: HashSet<u64>,
+    running: Option<(u64, CancelToken)>,
+}
+
+impl CancelState {
+    fn cancel(&mut self, id: u64) {
+        match &self.running {
+            Some((running_id, cancel)) if *running_id == id => cancel.cancel(),
+            _ => {
+                self.cancelled.insert(id);
+            }
+        }
+    }
+
+    ///Mark the request as running and return its token, None if it was cancelled before starting
+    fn start(&mut self, id: u64) -> Option<CancelToken> {
+        if self.cancelled.remove(&id) {
+            return None;
+        }
+
+        let cancel = CancelToken::default();
+        self.running = Some((id, cancel.clone()));
+        Some(cancel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ClosedPipe;
+
+    #[test]
+    fn cancel_before_start_skips_request() {
+        let mut state = CancelState::default();
+        state.cancel(3);
+
+        assert!(state.start(2).is_some());
+        assert!(state.start(3).is_none());
+        //The cancellation only applies once
+        assert!(state.start(3).is_some());
+    }
+
+    #[test]
+    fn cancel_running_request() {
+        let mut state = CancelState::default();
+        let cancel = state.start(1).expect("Not cancelled");
+
+        state.cancel(2);
+        assert!(!cancel.is_cancelled());
+
+        state.cancel(1);
+        assert!(cancel.is_cancelled());
+        assert_eq!(cancel.check(), Err(SlicerErrors::Cancelled));
+    }
+
+    ///A connection the test can read the responses from
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().expect("Output lock poisoned").write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn encode(requests: Vec<RequestMessage>) -> std::io::Cursor<Vec<u8>> {
+        let mut input = vec![];
+        for request in requests {
+            bincode::serialize_into(&mut input, &request).expect("Requests serialize");
+        }
+        std::io::Cursor::new(input)
+    }
+
+    fn requests() -> Vec<RequestMessage> {
+        vec![
+            RequestMessage {
+                id: 1,
+                request: Request::LoadSettings("missing.json".to_string()),
+            },
+            RequestMessage {
+                id: 2,
+                request: Request::Shutdown,
+            },
+        ]
+    }
+
+    #[test]
+    fn responses_are_tagged_with_the_request() {
+        let output = SharedOutput::default();
+
+        assert!(Server::default().serve(encode(requests()), Box::new(output.clone())));
+
+        let bytes = output.0.lock().expect("Output lock poisoned").clone();
+        let mut reader = std::io::Cursor::new(bytes);
+        let mut responses: Vec<ResponseMessage> = vec![];
+        while (reader.position() as usize) < reader.get_ref().len() {
+            responses.push(bincode::deserialize_from(&mut reader).expect("Valid response"));
+        }
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].id, 1);
+        assert!(matches!(
+            responses[0].message,
+            Message::Error(SlicerErrors::SettingsFileNotFound { .. })
+        ));
+        assert_eq!(responses[1].id, 2);
+        assert!(matches!(&responses[1].message, Message::StateUpdate(state) if state == "Done"));
+    }
+
+    #[test]
+    fn write_errors_end_the_connection() {
+        //The connection ends at the first response so the shutdown is never handled
+        assert!(!Server::default().serve(encode(requests()), Box::new(ClosedPipe)));
+    }
+}
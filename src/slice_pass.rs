@@ -1,24 +1,38 @@
+use crate::plotter::adaptive_cubic::adaptive_cubic_infill;
 use crate::plotter::lightning_infill::lightning_infill;
 use crate::plotter::support::Supporter;
 use crate::plotter::Plotter;
-use crate::utils::display_state_update;
+use crate::utils::{display_state_update, CancelToken, MessageSink, ProgressCounter};
 use crate::{Object, PolygonOperations, Settings, Slice};
 use geo::prelude::*;
 use geo::*;
 use gladius_shared::error::SlicerErrors;
+use gladius_shared::messages::ProgressStage;
 use gladius_shared::types::PartialInfillTypes;
 use rayon::prelude::*;
 
 pub trait ObjectPass {
-    fn pass(objects: &mut Vec<Object>, settings: &Settings, send_messages: bool);
+    fn pass(
+        objects: &mut Vec<Object>,
+        settings: &Settings,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<(), SlicerErrors>;
 }
 
 pub struct BrimPass {}
 
 impl ObjectPass for BrimPass {
-    fn pass(objects: &mut Vec<Object>, settings: &Settings, send_messages: bool) {
+    fn pass(
+        objects: &mut Vec<Object>,
+        settings: &Settings,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<(), SlicerErrors> {
+        cancel.check()?;
+
         if let Some(width) = &settings.brim_width {
-            display_state_update("Generating Moves: Brim", send_messages);
+            display_state_update("Generating Moves: Brim", messages);
             //Add to first object
 
             let first_layer_multipolygon: MultiPolygon<f64> = MultiPolygon(
@@ -45,38 +59,68 @@ impl ObjectPass for BrimPass {
                 .expect("Object needs a Slice")
                 .generate_brim(first_layer_multipolygon, *width);
         }
+        Ok(())
     }
 }
 
 pub struct SupportTowerPass {}
 
 impl ObjectPass for SupportTowerPass {
-    fn pass(objects: &mut Vec<Object>, settings: &Settings, send_messages: bool) {
+    fn pass(
+        objects: &mut Vec<Object>,
+        settings: &Settings,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<(), SlicerErrors> {
+        cancel.check()?;
+
         if let Some(support) = &settings.support {
-            display_state_update("Generating Support Towers", send_messages);
+            display_state_update("Generating Support Towers", messages);
             //Add to first object
 
-            objects.par_iter_mut().for_each(|obj| {
-                (1..obj.layers.len()).rev().for_each(|q| {
+            let progress = ProgressCounter::new(
+                ProgressStage::SupportTowers,
+                None,
+                objects
+                    .iter()
+                    .map(|obj| obj.layers.len().saturating_sub(1))
+                    .sum(),
+                messages,
+            );
+
+            objects.par_iter_mut().try_for_each(|obj| {
+                (1..obj.layers.len()).rev().try_for_each(|q| {
+                    cancel.check()?;
+
                     //todo Fix this, it feels hacky
                     if let [ref mut layer, ref mut above, ..] = &mut obj.layers[q - 1..=q] {
                         layer.add_support_polygons(above, support);
                     } else {
                         unreachable!()
                     }
-                });
-            });
+                    progress.increment();
+                    Ok(())
+                })
+            })?;
         }
+        Ok(())
     }
 }
 
 pub struct SkirtPass {}
 
 impl ObjectPass for SkirtPass {
-    fn pass(objects: &mut Vec<Object>, settings: &Settings, send_messages: bool) {
+    fn pass(
+        objects: &mut Vec<Object>,
+        settings: &Settings,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<(), SlicerErrors> {
+        cancel.check()?;
+
         //Handle Perimeters
         if let Some(skirt) = &settings.skirt {
-            display_state_update("Generating Moves: Skirt", send_messages);
+            display_state_update("Generating Moves: Skirt", messages);
             let convex_hull = objects
                 .iter()
                 .flat_map(|object| {
@@ -98,14 +142,18 @@ impl ObjectPass for SkirtPass {
                 .take(skirt.layers)
                 .for_each(|slice| slice.generate_skirt(&convex_hull, skirt, settings))
         }
+        Ok(())
     }
 }
 
 pub trait SlicePass {
+    ///Run the pass on the slices of the object with the index
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         settings: &Settings,
-        send_message: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors>;
 }
 
@@ -114,14 +162,24 @@ pub struct ShrinkPass {}
 impl SlicePass for ShrinkPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         _settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
-        display_state_update("Generating Moves: Shrink Layers", send_messages);
-        slices.par_iter_mut().for_each(|slice| {
+        display_state_update("Generating Moves: Shrink Layers", messages);
+        let progress = ProgressCounter::new(
+            ProgressStage::ShrinkLayers,
+            Some(object),
+            slices.len(),
+            messages,
+        );
+        slices.par_iter_mut().try_for_each(|slice| {
+            cancel.check()?;
             slice.shrink_layer();
-        });
-        Ok(())
+            progress.increment();
+            Ok(())
+        })
     }
 }
 
@@ -130,14 +188,31 @@ pub struct PerimeterPass {}
 impl SlicePass for PerimeterPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
-        display_state_update("Generating Moves: Perimeters", send_messages);
-        slices.par_iter_mut().for_each(|slice| {
-            slice.slice_perimeters_into_chains(settings.number_of_perimeters);
-        });
-        Ok(())
+        display_state_update("Generating Moves: Perimeters", messages);
+        let progress = ProgressCounter::new(
+            ProgressStage::Perimeters,
+            Some(object),
+            slices.len(),
+            messages,
+        );
+        slices
+            .par_iter_mut()
+            .enumerate()
+            .try_for_each(|(layer_num, slice)| {
+                cancel.check()?;
+                if settings.is_spiral_layer(layer_num) {
+                    slice.slice_spiral_perimeter_into_chains();
+                } else {
+                    slice.slice_perimeters_into_chains(settings.number_of_perimeters);
+                }
+                progress.increment();
+                Ok(())
+            })
     }
 }
 
@@ -146,16 +221,26 @@ pub struct BridgingPass {}
 impl SlicePass for BridgingPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         _settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
-        display_state_update("Generating Moves: Bridging", send_messages);
-        (1..slices.len()).for_each(|q| {
+        display_state_update("Generating Moves: Bridging", messages);
+        let progress = ProgressCounter::new(
+            ProgressStage::Bridging,
+            Some(object),
+            slices.len().saturating_sub(1),
+            messages,
+        );
+        (1..slices.len()).try_for_each(|q| {
+            cancel.check()?;
             let below = slices[q - 1].main_polygon.clone();
 
             slices[q].fill_solid_bridge_area(&below);
-        });
-        Ok(())
+            progress.increment();
+            Ok(())
+        })
     }
 }
 pub struct TopLayerPass {}
@@ -163,16 +248,26 @@ pub struct TopLayerPass {}
 impl SlicePass for TopLayerPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         _settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
-        display_state_update("Generating Moves: Top Layer", send_messages);
-        (0..slices.len() - 1).for_each(|q| {
+        display_state_update("Generating Moves: Top Layer", messages);
+        let progress = ProgressCounter::new(
+            ProgressStage::TopLayer,
+            Some(object),
+            slices.len().saturating_sub(1),
+            messages,
+        );
+        (0..slices.len() - 1).try_for_each(|q| {
+            cancel.check()?;
             let above = slices[q + 1].main_polygon.clone();
 
             slices[q].fill_solid_top_layer(&above, q);
-        });
-        Ok(())
+            progress.increment();
+            Ok(())
+        })
     }
 }
 
@@ -181,17 +276,28 @@ pub struct TopAndBottomLayersPass {}
 impl SlicePass for TopAndBottomLayersPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
         let top_layers = settings.top_layers;
         let bottom_layers = settings.bottom_layers;
 
+        //Every layer is either in the range below or filled solid
+        let progress = ProgressCounter::new(
+            ProgressStage::TopAndBottomLayers,
+            Some(object),
+            slices.len(),
+            messages,
+        );
+
         //Make sure at least 1 layer will not be solid
         if slices.len() > bottom_layers + top_layers {
-            display_state_update("Generating Moves: Above and below support", send_messages);
+            display_state_update("Generating Moves: Above and below support", messages);
 
-            (bottom_layers..slices.len() - top_layers).for_each(|q| {
+            (bottom_layers..slices.len() - top_layers).try_for_each(|q| {
+                cancel.check()?;
                 let below = if bottom_layers != 0 {
                     Some(
                         slices[(q - bottom_layers + 1)..q]
@@ -236,7 +342,9 @@ impl SlicePass for TopAndBottomLayersPass {
                         .expect("Bounds Checked above")
                         .fill_solid_subtracted_area(&intersection, q);
                 }
-            });
+                progress.increment();
+                Ok(())
+            })?;
         }
 
         let slice_count = slices.len();
@@ -248,10 +356,12 @@ impl SlicePass for TopAndBottomLayersPass {
                 *layer_num < settings.bottom_layers
                     || settings.top_layers + *layer_num + 1 > slice_count
             })
-            .for_each(|(layer_num, slice)| {
+            .try_for_each(|(layer_num, slice)| {
+                cancel.check()?;
                 slice.fill_remaining_area(true, layer_num);
-            });
-        Ok(())
+                progress.increment();
+                Ok(())
+            })
     }
 }
 
@@ -260,12 +370,19 @@ pub struct SupportPass {}
 impl SlicePass for SupportPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         settings: &Settings,
-        _send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
         if let Some(support) = &settings.support {
+            let progress =
+                ProgressCounter::new(ProgressStage::Support, Some(object), slices.len(), messages);
+
             for slice in slices.iter_mut() {
+                cancel.check()?;
                 slice.fill_support_polygons(support);
+                progress.increment();
             }
         }
         Ok(())
@@ -277,19 +394,29 @@ pub struct FillAreaPass {}
 impl SlicePass for FillAreaPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         _settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
-        display_state_update("Generating Moves: Fill Areas", send_messages);
+        display_state_update("Generating Moves: Fill Areas", messages);
+        let progress = ProgressCounter::new(
+            ProgressStage::FillAreas,
+            Some(object),
+            slices.len(),
+            messages,
+        );
 
         //Fill all remaining areas
         slices
             .par_iter_mut()
             .enumerate()
-            .for_each(|(layer_num, slice)| {
+            .try_for_each(|(layer_num, slice)| {
+                cancel.check()?;
                 slice.fill_remaining_area(false, layer_num);
-            });
-        Ok(())
+                progress.increment();
+                Ok(())
+            })
     }
 }
 pub struct LightningFillPass {}
@@ -297,32 +424,64 @@ pub struct LightningFillPass {}
 impl SlicePass for LightningFillPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        _object: usize,
         settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
         if settings.partial_infill_type == PartialInfillTypes::Lightning {
-            display_state_update("Generating Moves: Lightning Infill", send_messages);
+            display_state_update("Generating Moves: Lightning Infill", messages);
 
+            cancel.check()?;
             lightning_infill(slices);
         }
         Ok(())
     }
 }
 
+pub struct AdaptiveCubicFillPass {}
+
+impl SlicePass for AdaptiveCubicFillPass {
+    fn pass(
+        slices: &mut Vec<Slice>,
+        _object: usize,
+        settings: &Settings,
+        cancel: &CancelToken,
+        messages: MessageSink,
+    ) -> Result<(), SlicerErrors> {
+        if settings.partial_infill_type == PartialInfillTypes::AdaptiveCubic {
+            display_state_update("Generating Moves: Adaptive Cubic Infill", messages);
+
+            adaptive_cubic_infill(slices, cancel)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct OrderPass {}
 
 impl SlicePass for OrderPass {
     fn pass(
         slices: &mut Vec<Slice>,
+        object: usize,
         _settings: &Settings,
-        send_messages: bool,
+        cancel: &CancelToken,
+        messages: MessageSink,
     ) -> Result<(), SlicerErrors> {
-        display_state_update("Generating Moves: Order Chains", send_messages);
+        display_state_update("Generating Moves: Order Chains", messages);
+        let progress = ProgressCounter::new(
+            ProgressStage::OrderChains,
+            Some(object),
+            slices.len(),
+            messages,
+        );
 
         //Fill all remaining areas
-        slices.par_iter_mut().for_each(|slice| {
+        slices.par_iter_mut().try_for_each(|slice| {
+            cancel.check()?;
             slice.order_chains();
-        });
-        Ok(())
+            progress.increment();
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,425 @@
+use crate::bounds_checking::check_model_bounds;
+use crate::cache::SliceCache;
+use crate::calculation::calculate_values;
+use crate::converter::{convert_to_file, convert_to_string};
+use crate::generate_commands;
+use crate::input::{load_models, load_settings};
+use crate::repair::repair_models;
+use crate::utils::{display_state_update, show_warning_message, CancelToken, MessageSink};
+use gladius_shared::error::SlicerErrors;
+use gladius_shared::messages::{Message, ProgressUpdate};
+use gladius_shared::settings::{Settings, SettingsValidationResult};
+use gladius_shared::types::{CalculatedValues, Command};
+use gladius_shared::warning::SlicerWarnings;
+use gladius_shared::writer::ThreeMFWriter;
+use log::debug;
+use std::sync::{Mutex, PoisonError};
+
+type MessageCallback = Box<dyn FnMut(&Message) + Send>;
+
+///Where the settings of a slice come from
+#[derive(Default)]
+enum SettingsSource {
+    #[default]
+    Default,
+    File(String),
+    Settings(Box<Settings>),
+}
+
+///Builder that loads, slices and converts models, returning errors instead of exiting
+#[derive(Default)]
+pub struct Slicer {
+    settings: SettingsSource,
+    inputs: Vec<String>,
+    project_settings: bool,
+    arrange: bool,
+    arrange_rotate: bool,
+    export_3mf: Option<String>,
+    output_file: Option<String>,
+    cancel: CancelToken,
+    callbacks: Vec<MessageCallback>,
+}
+
+///The results of a slice
+#[derive(Debug, Clone)]
+pub struct SliceResult {
+    ///The optimized commands of the print
+    pub commands: Vec<Command>,
+
+    ///Time and filament used by the print
+    pub calculated_values: CalculatedValues,
+
+    ///The settings used, including any project settings
+    pub settings: Settings,
+
+    ///The G-code, None if it was written to the output file
+    pub gcode: Option<String>,
+}
+
+impl Slicer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Use the settings instead of the defaults
+    pub fn settings(mut self, settings: Settings) -> Self {
+        self.settings = SettingsSource::Settings(Box::new(settings));
+        self
+    }
+
+    ///Load the settings from the settings file instead of using the defaults
+    pub fn settings_file(mut self, filepath: impl Into<String>) -> Self {
+        self.settings = SettingsSource::File(filepath.into());
+        self
+    }
+
+    ///Add an input, in the same format as the command line inputs
+    pub fn input(mut self, input: impl Into<String>) -> Self {
+        self.inputs.push(input.into());
+        self
+    }
+
+    pub fn inputs(mut self, inputs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.inputs.extend(inputs.into_iter().map(Into::into));
+        self
+    }
+
+    ///Use the slicer settings stored in 3MF project files
+    pub fn project_settings(mut self, project_settings: bool) -> Self {
+        self.project_settings = project_settings;
+        self
+    }
+
+    ///Arrange the automatically placed models so they do not overlap
+    pub fn arrange(mut self, arrange: bool) -> Self {
+        self.arrange = arrange;
+        self
+    }
+
+    ///Allow models to be rotated around z when arranging them
+    pub fn arrange_rotate(mut self, arrange_rotate: bool) -> Self {
+        self.arrange_rotate = arrange_rotate;
+        self
+    }
+
+    ///Save the placed models and settings to a 3MF file before slicing
+    pub fn export_3mf(mut self, filepath: impl Into<String>) -> Self {
+        self.export_3mf = Some(filepath.into());
+        self
+    }
+
+    ///Write the G-code to the file instead of returning it
+    pub fn output_file(mut self, filepath: impl Into<String>) -> Self {
+        self.output_file = Some(filepath.into());
+        self
+    }
+
+    ///Token that stops the slice with the cancelled error when cancelled
+    pub fn cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    ///Called with every message sent while slicing
+    pub fn on_message(mut self, callback: impl FnMut(&Message) + Send + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    pub fn on_progress(self, mut callback: impl FnMut(&ProgressUpdate) + Send + 'static) -> Self {
+        self.on_message(move |message| {
+            if let Message::Progress(progress) = message {
+                callback(progress)
+            }
+        })
+    }
+
+    pub fn on_warning(self, mut callback: impl FnMut(&SlicerWarnings) + Send + 'static) -> Self {
+        self.on_message(move |message| {
+            if let Message::Warning(warning) = message {
+                callback(warning)
+            }
+        })
+    }
+
+    pub fn on_state_update(self, mut callback: impl FnMut(&str) + Send + 'static) -> Self {
+        self.on_message(move |message| {
+            if let Message::StateUpdate(state) = message {
+                callback(state)
+            }
+        })
+    }
+
+    ///Load, repair and slice the inputs. Without callbacks warnings and state updates are logged
+    pub fn slice(mut self) -> Result<SliceResult, SlicerErrors> {
+        if self.callbacks.is_empty() {
+            return self.run(MessageSink::default());
+        }
+
+        //Messages are sent from the threads of the slice so the callbacks are called one at a time
+        let callbacks = Mutex::new(std::mem::take(&mut self.callbacks));
+        let send = |message: Message| {
+            let mut callbacks = callbacks.lock().unwrap_or_else(PoisonError::into_inner);
+            for callback in callbacks.iter_mut() {
+                callback(&message)
+            }
+        };
+
+        self.run(MessageSink::new(&send))
+    }
+
+    fn run(self, messages: MessageSink) -> Result<SliceResult, SlicerErrors> {
+        if self.inputs.is_empty() {
+            return Err(SlicerErrors::NoInputProvided);
+        }
+
+        display_state_update("Loading Inputs", messages);
+        let settings = match self.settings {
+            SettingsSource::Default => Settings::default(),
+            SettingsSource::File(filepath) => load_settings(&filepath)?,
+            SettingsSource::Settings(settings) => *settings,
+        };
+
+        let (models, infos, settings, input_warnings) = load_models(
+            settings,
+            &self.inputs,
+            self.project_settings,
+            self.arrange,
+            self.arrange_rotate,
+            messages,
+        )?;
+
+        for warning in input_warnings {
+            report_warning(warning, messages);
+        }
+
+        display_state_update("Repairing Models", messages);
+        let (models, repair_warnings) = repair_models(models);
+
+        for warning in repair_warnings {
+            report_warning(warning, messages);
+        }
+
+        if let Some(filepath) = &self.export_3mf {
+            display_state_update("Exporting 3MF", messages);
+            ThreeMFWriter {}.write(filepath, &models, &infos, &settings)?;
+        }
+
+        check_model_bounds(&models, &settings)?;
+
+        match settings.validate_settings() {
+            SettingsValidationResult::NoIssue => {}
+            SettingsValidationResult::Warning(slicer_warning) => {
+                report_warning(slicer_warning, messages)
+            }
+            SettingsValidationResult::Error(slicer_error) => return Err(slicer_error),
+        }
+
+        let commands = generate_commands(
+            &models,
+            &infos,
+            &settings,
+            &mut SliceCache::default(),
+            &self.cancel,
+            messages,
+        )?;
+
+        if messages.is_enabled() {
+            messages.send(Message::Commands(commands.clone()));
+        }
+        display_state_update("Calculate Values", messages);
+
+        let calculated_values = calculate_values(&commands, &settings);
+
+        messages.send(Message::CalculatedValues(calculated_values.clone()));
+
+        display_state_update("Outputting G-code", messages);
+        debug!("Converting {} Moves", commands.len());
+        let gcode = if let Some(filepath) = &self.output_file {
+            convert_to_file(&commands, &settings, filepath, &self.cancel, messages)?;
+            None
+        } else {
+            Some(convert_to_string(
+                &commands,
+                &settings,
+                &self.cancel,
+                messages,
+            )?)
+        };
+
+        Ok(SliceResult {
+            commands,
+            calculated_values,
+            settings,
+            gcode,
+        })
+    }
+}
+
+fn report_warning(warning: SlicerWarnings, messages: MessageSink) {
+    if messages.is_enabled() {
+        messages.send(Message::Warning(warning))
+    } else {
+        show_warning_message(warning)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repair::Model;
+    use crate::test_utils::cube;
+    use gladius_shared::messages::ProgressStage;
+    use gladius_shared::types::ObjectInfo;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    ///Write the models to a 3MF file and return the input that loads them in place
+    fn plate_input(name: &str, models: &[Model]) -> String {
+        let filepath = std::env::temp_dir()
+            .join(format!("gladius_{}_{}.3mf", std::process::id(), name))
+            .to_string_lossy()
+            .to_string();
+
+        ThreeMFWriter {}
+            .write(
+                &filepath,
+                models,
+                &vec![ObjectInfo::default(); models.len()],
+                &Settings::default(),
+            )
+            .expect("Temp file is writable");
+
+        format!(
+            "{{\"Raw\":[{:?},[[1.0,0.0,0.0,0.0],[0.0,1.0,0.0,0.0],[0.0,0.0,1.0,0.0],[0.0,0.0,0.0,1.0]]]}}",
+            filepath
+        )
+    }
+
+    #[test]
+    fn slice_without_inputs_is_an_error() {
+        assert_eq!(
+            Slicer::new().slice().map(|_| ()),
+            Err(SlicerErrors::NoInputProvided)
+        );
+    }
+
+    #[test]
+    fn errors_are_returned_after_callbacks() {
+        let states = Arc::new(Mutex::new(vec![]));
+        let callback_states = states.clone();
+
+        let result = Slicer::new()
+            .input("{\"Auto\":\"missing.stl\"}")
+            .on_state_update(move |state| {
+                callback_states
+                    .lock()
+                    .expect("States lock poisoned")
+                    .push(state.to_string())
+            })
+            .slice();
+
+        assert_eq!(
+            result.map(|_| ()),
+            Err(SlicerErrors::ObjectFileNotFound {
+                filepath: "missing.stl".to_string()
+            })
+        );
+        assert_eq!(
+            *states.lock().expect("States lock poisoned"),
+            vec!["Loading Inputs".to_string()]
+        );
+    }
+
+    #[test]
+    fn progress_is_reported_per_object() {
+        //Objects of different heights have different numbers of layers
+        let input = plate_input(
+            "progress",
+            &[cube((50.0, 50.0), 10.0), cube((100.0, 100.0), 20.0)],
+        );
+
+        let updates = Arc::new(Mutex::new(vec![]));
+        let callback_updates = updates.clone();
+
+        Slicer::new()
+            .input(input)
+            .on_progress(move |progress| {
+                callback_updates
+                    .lock()
+                    .expect("Progress lock poisoned")
+                    .push(progress.clone())
+            })
+            .slice()
+            .expect("Cubes slice");
+
+        let mut counts: HashMap<(ProgressStage, Option<usize>), (usize, usize)> = HashMap::new();
+
+        for update in updates.lock().expect("Progress lock poisoned").iter() {
+            let (completed, total) = counts
+                .entry((update.stage, update.object))
+                .or_insert((0, update.total));
+
+            //Each counter keeps its total and never goes backwards
+            assert_eq!(*total, update.total);
+            assert!(*completed <= update.completed);
+            *completed = update.completed;
+        }
+
+        assert!(counts.values().all(|(completed, total)| completed == total));
+
+        let slicing = |object| counts[&(ProgressStage::Slicing, Some(object))].1;
+        assert!(slicing(0) > 0);
+        assert!(slicing(1) > slicing(0));
+        assert!(counts.contains_key(&(ProgressStage::Perimeters, Some(1))));
+        assert_eq!(counts[&(ProgressStage::Objects, None)].1, 2);
+    }
+
+    #[test]
+    fn slices_run_at_the_same_time_with_their_own_messages() {
+        let slices: Vec<_> = [10.0, 20.0]
+            .iter()
+            .map(|&size| {
+                let input =
+                    plate_input(&format!("concurrent_{}", size), &[cube((50.0, 50.0), size)]);
+
+                std::thread::spawn(move || {
+                    let messages = Arc::new(Mutex::new(vec![]));
+                    let callback_messages = messages.clone();
+
+                    let result = Slicer::new()
+                        .input(input)
+                        .on_message(move |message| {
+                            callback_messages
+                                .lock()
+                                .expect("Messages lock poisoned")
+                                .push(message.clone())
+                        })
+                        .slice()
+                        .expect("Cube slices");
+
+                    let messages = messages.lock().expect("Messages lock poisoned").clone();
+                    (result, messages)
+                })
+            })
+            .collect();
+
+        for slice in slices {
+            let (result, messages) = slice.join().expect("Slice thread finished");
+
+            let calculated_values: Vec<_> = messages
+                .iter()
+                .filter_map(|message| match message {
+                    Message::CalculatedValues(values) => Some(values),
+                    _ => None,
+                })
+                .collect();
+
+            assert_eq!(calculated_values.len(), 1);
+            assert_eq!(
+                calculated_values[0].total_time,
+                result.calculated_values.total_time
+            );
+        }
+    }
+}
@@ -1,11 +1,34 @@
+use crate::utils::{lerp, CancelToken, MessageSink, ProgressCounter};
 use crate::*;
+use gladius_shared::messages::ProgressStage;
+use gladius_shared::settings::AdaptiveLayerHeightSettings;
 
-pub fn slice(towers: &[TriangleTower], settings: &Settings) -> Result<Vec<Object>, SlicerErrors> {
+pub fn slice(
+    towers: &[TriangleTower],
+    infos: Vec<ObjectInfo>,
+    settings: &Settings,
+    cancel: &CancelToken,
+    messages: MessageSink,
+) -> Result<Vec<Object>, SlicerErrors> {
     towers
         .par_iter()
-        .map(|tower| {
+        .zip(infos)
+        .enumerate()
+        .map(|(index, (tower, info))| {
+            let object_settings = info
+                .settings
+                .clone()
+                .map(|overrides| settings.with_overrides(overrides))
+                .transpose()?;
+            let settings = object_settings.as_ref().unwrap_or(settings);
+
             let mut tower_iter = TriangleTowerIterator::new(tower);
 
+            let mut adaptive = settings
+                .adaptive_layer_height
+                .as_ref()
+                .map(|adaptive_settings| AdaptiveLayerHeight::new(tower, adaptive_settings));
+
             let mut layer = 0.0;
 
             let mut first_layer = true;
@@ -14,9 +37,20 @@ pub fn slice(towers: &[TriangleTower], settings: &Settings) -> Result<Vec<Object
                 std::iter::repeat(())
                     .enumerate()
                     .map(|(layer_count, _)| {
+                        cancel.check()?;
+
                         //Advance to the correct height
-                        let layer_height =
-                            settings.get_layer_settings(layer_count, layer).layer_height;
+                        let layer_height = match (
+                            settings.get_layer_changes(layer_count, layer).layer_height,
+                            adaptive.as_mut(),
+                        ) {
+                            //explicit layer settings take priority over adaptive heights
+                            (Some(layer_height), _) => layer_height,
+                            (None, Some(adaptive)) if !first_layer => adaptive.next_height(layer),
+                            (None, _) => {
+                                settings.get_layer_settings(layer_count, layer).layer_height
+                            }
+                        };
 
                         let bottom_height = layer;
                         layer += layer_height / 2.0;
@@ -41,10 +75,15 @@ pub fn slice(towers: &[TriangleTower], settings: &Settings) -> Result<Vec<Object
 
             let points = res_points?;
 
+            let progress =
+                ProgressCounter::new(ProgressStage::Slicing, Some(index), points.len(), messages);
+
             let slices: Result<Vec<Slice>, SlicerErrors> = points
                 .par_iter()
                 .enumerate()
                 .map(|(count, (bot, top, layer_loops))| {
+                    cancel.check()?;
+
                     //Add this slice to the
                     let slice = Slice::from_multiple_point_loop(
                         layer_loops
@@ -61,11 +100,133 @@ pub fn slice(towers: &[TriangleTower], settings: &Settings) -> Result<Vec<Object
                         count,
                         settings,
                     );
+                    progress.increment();
                     slice
                 })
                 .collect();
 
-            Ok(Object { layers: slices? })
+            Ok(Object {
+                layers: slices?,
+                info,
+                settings: object_settings,
+            })
         })
         .collect()
 }
+
+///Chooses layer heights from the slope of the triangles the layer passes through
+struct AdaptiveLayerHeight<'a> {
+    slopes: &'a [TriangleSlope],
+    settings: &'a AdaptiveLayerHeightSettings,
+    next_slope: usize,
+    active: Vec<TriangleSlope>,
+}
+
+impl<'a> AdaptiveLayerHeight<'a> {
+    fn new(tower: &'a TriangleTower, settings: &'a AdaptiveLayerHeightSettings) -> Self {
+        AdaptiveLayerHeight {
+            slopes: tower.get_triangle_slopes(),
+            settings,
+            next_slope: 0,
+            active: vec![],
+        }
+    }
+
+    ///Get the height of the layer starting at bottom, heights must be requested in increasing order
+    fn next_height(&mut self, bottom: f64) -> f64 {
+        let max = self.settings.max_layer_height;
+        let min = self.settings.min_layer_height.min(max);
+
+        //the largest step allowed between the layer and the surface
+        let max_cusp = lerp(max, min, self.settings.quality.clamp(0.0, 1.0));
+
+        //add triangles that start below the thickest possible layer
+        while self.next_slope < self.slopes.len()
+            && self.slopes[self.next_slope].bottom < bottom + max
+        {
+            self.active.push(self.slopes[self.next_slope]);
+            self.next_slope += 1;
+        }
+
+        //remove triangles that are entirely below the layer
+        self.active.retain(|slope| slope.top > bottom);
+
+        self.active
+            .iter()
+            .fold(max, |height, slope| {
+                if slope.normal_z * height > max_cusp && slope.bottom < bottom + height {
+                    height.min(max_cusp / slope.normal_z)
+                } else {
+                    height
+                }
+            })
+            .clamp(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> AdaptiveLayerHeightSettings {
+        AdaptiveLayerHeightSettings {
+            min_layer_height: 0.1,
+            max_layer_height: 0.3,
+            quality: 0.5,
+        }
+    }
+
+    fn slope(bottom: f64, top: f64, normal_z: f64) -> TriangleSlope {
+        TriangleSlope {
+            bottom,
+            top,
+            normal_z,
+        }
+    }
+
+    #[test]
+    fn vertical_walls_use_max_height() {
+        let settings = settings();
+        let slopes = vec![slope(0.0, 10.0, 0.0)];
+        let mut adaptive = AdaptiveLayerHeight {
+            slopes: &slopes,
+            settings: &settings,
+            next_slope: 0,
+            active: vec![],
+        };
+
+        assert_eq!(adaptive.next_height(1.0), 0.3);
+    }
+
+    #[test]
+    fn shallow_slopes_use_thinner_layers() {
+        let settings = settings();
+        let slopes = vec![slope(0.0, 10.0, 0.0), slope(2.0, 3.0, 0.9)];
+        let mut adaptive = AdaptiveLayerHeight {
+            slopes: &slopes,
+            settings: &settings,
+            next_slope: 0,
+            active: vec![],
+        };
+
+        assert_eq!(adaptive.next_height(0.5), 0.3);
+        let height = adaptive.next_height(1.9);
+        assert!((height - 0.2 / 0.9).abs() < 1e-9);
+        assert_eq!(adaptive.next_height(3.5), 0.3);
+    }
+
+    #[test]
+    fn flat_surfaces_clamp_to_min_height() {
+        let mut settings = settings();
+        settings.quality = 1.0;
+        let slopes = vec![slope(1.0, 1.05, 1.0)];
+        let mut adaptive = AdaptiveLayerHeight {
+            slopes: &slopes,
+            settings: &settings,
+            next_slope: 0,
+            active: vec![],
+        };
+
+        assert_eq!(adaptive.next_height(0.9), 0.1);
+    }
+}
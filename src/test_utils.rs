@@ -0,0 +1,42 @@
+use crate::repair::Model;
+use gladius_shared::loader::lowest_vertex_first;
+use gladius_shared::types::{IndexedTriangle, Vertex};
+
+///A closed cuboid with its lowest corner at the origin given and the size along each axis
+pub(crate) fn cuboid(origin: (f64, f64, f64), size: (f64, f64, f64)) -> Model {
+    let mut vertices = vec![];
+    for x in [origin.0, origin.0 + size.0] {
+        for y in [origin.1, origin.1 + size.1] {
+            for z in [origin.2, origin.2 + size.2] {
+                vertices.push(Vertex { x, y, z });
+            }
+        }
+    }
+
+    //Vertex index is x * 4 + y * 2 + z, wound to face outwards
+    let faces = [
+        [0, 1, 3, 2],
+        [4, 6, 7, 5],
+        [0, 4, 5, 1],
+        [2, 3, 7, 6],
+        [0, 2, 6, 4],
+        [1, 5, 7, 3],
+    ];
+    let triangles = faces
+        .iter()
+        .flat_map(|&[a, b, c, d]| {
+            vec![
+                IndexedTriangle { verts: [a, b, c] },
+                IndexedTriangle { verts: [a, c, d] },
+            ]
+        })
+        .map(|triangle| lowest_vertex_first(triangle, &vertices))
+        .collect();
+
+    (vertices, triangles)
+}
+
+///A closed cube standing on the bed with its lowest corner at the origin given
+pub(crate) fn cube(origin: (f64, f64), size: f64) -> Model {
+    cuboid((origin.0, origin.1, 0.0), (size, size, size))
+}
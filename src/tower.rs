@@ -46,6 +46,18 @@ fn lerp(a: f64, b: f64, f: f64) -> f64 {
 pub struct TriangleTower {
     vertices: Vec<Vertex>,
     tower_vertices: Vec<TowerVertex>,
+    triangle_slopes: Vec<TriangleSlope>,
+}
+
+/// The height range and steepness of a triangle in the tower
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleSlope {
+    /// Height of the lowest point of the triangle
+    pub bottom: f64,
+    /// Height of the highest point of the triangle
+    pub top: f64,
+    /// Absolute z component of the unit normal, 0 for vertical walls and 1 for flat faces
+    pub normal_z: f64,
 }
 
 impl TriangleTower {
@@ -80,6 +92,34 @@ impl TriangleTower {
             }
         }
 
+        //the slope of each triangle sorted from lowest to highest
+        let mut triangle_slopes: Vec<TriangleSlope> = triangles
+            .iter()
+            .filter_map(|index_tri| {
+                let [a, b, c] = index_tri.verts.map(|index| vertices[index]);
+                let (ux, uy, uz) = (b.x - a.x, b.y - a.y, b.z - a.z);
+                let (vx, vy, vz) = (c.x - a.x, c.y - a.y, c.z - a.z);
+                let (nx, ny, nz) = (uy * vz - uz * vy, uz * vx - ux * vz, ux * vy - uy * vx);
+                let length = (nx * nx + ny * ny + nz * nz).sqrt();
+
+                if length > 0.0 {
+                    Some(TriangleSlope {
+                        bottom: a.z,
+                        top: b.z.max(c.z),
+                        normal_z: (nz / length).abs(),
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        triangle_slopes.sort_by(|a, b| {
+            a.bottom
+                .partial_cmp(&b.bottom)
+                .expect("STL ERROR: No Points should have NAN values")
+        });
+
         //for each triangle event, add it to the lowest vertex and
         //create a list of all vertices and there above edges
 
@@ -108,9 +148,14 @@ impl TriangleTower {
         Ok(Self {
             vertices,
             tower_vertices,
+            triangle_slopes,
         })
     }
 
+    pub fn get_triangle_slopes(&self) -> &[TriangleSlope] {
+        &self.triangle_slopes
+    }
+
     pub fn get_height_of_vertex(&self, index: usize) -> f64 {
         if index >= self.tower_vertices.len() {
             f64::INFINITY
@@ -136,6 +181,7 @@ impl TowerRing {
         self.elements.first() == self.elements.last() && self.elements.len() > 3
     }
 
+    #[cfg(test)]
     fn join_rings(mut first: TowerRing, second: TowerRing) -> Self {
         TowerRing::join_rings_in_place(&mut first, second);
 
@@ -447,10 +493,13 @@ impl<'s> TriangleTowerIterator<'s> {
             // }
             self.tower_vert_index += 1;
 
-            for ring in &self.active_rings {
-                if !ring.is_complete_ring() {
-                    return Err(SlicerErrors::TowerGeneration);
-                }
+            if self
+                .active_rings
+                .iter()
+                .any(|ring| !ring.is_complete_ring())
+            {
+                let height = self.tower.vertices[pop_tower_vert.start_index].z;
+                return Err(self.open_ring_error(height));
             }
         }
 
@@ -459,6 +508,54 @@ impl<'s> TriangleTowerIterator<'s> {
         Ok(())
     }
 
+    /// Create the error describing the rings that failed to close at the height
+    fn open_ring_error(&self, height: f64) -> SlicerErrors {
+        let open_rings: Vec<&TowerRing> = self
+            .active_rings
+            .iter()
+            .filter(|ring| !ring.is_complete_ring())
+            .collect();
+
+        let mut triangles: Vec<usize> = open_rings
+            .iter()
+            .flat_map(|ring| ring.elements.iter())
+            .filter_map(|element| match element {
+                TowerRingElement::Face { triangle_index } => Some(*triangle_index),
+                TowerRingElement::Edge { .. } => None,
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles.dedup();
+
+        //The open ends of a ring are where it failed to close
+        let points = open_rings
+            .iter()
+            .flat_map(|ring| [ring.elements.first(), ring.elements.last()])
+            .flatten()
+            .filter_map(|element| match element {
+                TowerRingElement::Edge {
+                    start_index,
+                    end_index,
+                } => {
+                    let start = self.tower.vertices[*start_index];
+                    let end = self.tower.vertices[*end_index];
+                    if start.z == end.z {
+                        Some(start)
+                    } else {
+                        Some(line_z_intersection(height, start, end))
+                    }
+                }
+                TowerRingElement::Face { .. } => None,
+            })
+            .collect();
+
+        SlicerErrors::TowerGeneration {
+            height,
+            triangles,
+            points,
+        }
+    }
+
     pub fn get_points(&self) -> Vec<Vec<Vertex>> {
         self.active_rings
             .iter()
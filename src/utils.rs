@@ -1,10 +1,113 @@
 use geo::Coord;
 use gladius_shared::error::SlicerErrors;
-use gladius_shared::messages::Message;
+use gladius_shared::messages::{Message, ProgressStage, ProgressUpdate};
 use gladius_shared::warning::SlicerWarnings;
 use log::{error, info, warn};
 use nalgebra::Vector2;
-use std::io::Write;
+use serde::Serialize;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+///Shortest time between progress messages for a stage
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+///How messages are encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageFormat {
+    ///Bincode encoded messages
+    Bincode,
+
+    ///JSON encoded messages, one per line
+    Json,
+}
+
+static MESSAGE_FORMAT: OnceLock<MessageFormat> = OnceLock::new();
+
+///Set the encoding of messages, defaults to bincode if never set
+pub fn set_message_format(format: MessageFormat) {
+    MESSAGE_FORMAT
+        .set(format)
+        .expect("Message format only set once");
+}
+
+pub fn get_message_format() -> MessageFormat {
+    MESSAGE_FORMAT
+        .get()
+        .copied()
+        .unwrap_or(MessageFormat::Bincode)
+}
+
+///Write the message to the writer in the format, returning any error from the writer
+pub fn write_message(
+    writer: &mut impl Write,
+    message: &impl Serialize,
+    format: MessageFormat,
+) -> std::io::Result<()> {
+    match format {
+        MessageFormat::Bincode => {
+            bincode::serialize_into(&mut *writer, message).map_err(|err| match *err {
+                bincode::ErrorKind::Io(err) => err,
+                err => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+            })?
+        }
+        MessageFormat::Json => {
+            serde_json::to_writer(&mut *writer, message)?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()
+}
+
+///A connection that has been closed by the other end
+#[cfg(test)]
+pub(crate) struct ClosedPipe;
+
+#[cfg(test)]
+impl Write for ClosedPipe {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+///Where the messages of a single slice are sent, such as the callbacks of a library user or a
+///server connection. Without a destination state updates are logged and other messages dropped
+#[derive(Clone, Copy, Default)]
+pub struct MessageSink<'a>(Option<&'a (dyn Fn(Message) + Sync)>);
+
+impl<'a> MessageSink<'a> {
+    pub fn new(send: &'a (dyn Fn(Message) + Sync)) -> Self {
+        MessageSink(Some(send))
+    }
+
+    ///Whether messages are sent anywhere
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn send(&self, message: Message) {
+        if let Some(send) = self.0 {
+            send(message);
+        }
+    }
+}
+
+///Write the message to standard out in the message format
+pub fn print_message(message: &Message) {
+    let stdout = std::io::stdout();
+    if let Err(err) = write_message(
+        &mut BufWriter::new(stdout.lock()),
+        message,
+        get_message_format(),
+    ) {
+        error!("Could not write the message to standard out: {}", err);
+    }
+}
 
 pub fn show_error_message(error: SlicerErrors) {
     let (error_code, message) = error.get_code_and_message();
@@ -17,12 +120,7 @@ pub fn show_error_message(error: SlicerErrors) {
     error!("\n\n\n");
 }
 pub fn send_error_message(error: SlicerErrors) {
-    let stdout = std::io::stdout();
-    let mut stdio_lock = stdout.lock();
-
-    let message = Message::Error(error);
-    bincode::serialize_into(&mut stdio_lock, &message).expect("Write Limit should not be hit");
-    stdio_lock.flush().expect("Standard Out should be limited");
+    print_message(&Message::Error(error));
 }
 
 pub fn show_warning_message(warning: SlicerWarnings) {
@@ -36,25 +134,100 @@ pub fn show_warning_message(warning: SlicerWarnings) {
     warn!("\n\n\n");
 }
 pub fn send_warning_message(warning: SlicerWarnings) {
-    let stdout = std::io::stdout();
-    let mut stdio_lock = stdout.lock();
-    let message = Message::Warning(warning);
-    bincode::serialize_into(&mut stdio_lock, &message).expect("Write Limit should not be hit");
-    stdio_lock.flush().expect("Standard Out should be limited");
-}
-
-pub fn display_state_update(state_message: &str, send_message: bool) {
-    if send_message {
-        let stdout = std::io::stdout();
-        let mut stdio_lock = stdout.lock();
-        let message = Message::StateUpdate(state_message.to_string());
-        bincode::serialize_into(&mut stdio_lock, &message).expect("Write Limit should not be hit");
-        stdio_lock.flush().expect("Standard Out should be limited");
+    print_message(&Message::Warning(warning));
+}
+
+pub fn display_state_update(state_message: &str, messages: MessageSink) {
+    if messages.is_enabled() {
+        messages.send(Message::StateUpdate(state_message.to_string()));
     } else {
         info!("{}", state_message);
     }
 }
 
+///Counts the completed work of a stage and sends progress messages at a limited rate
+pub struct ProgressCounter<'a> {
+    stage: ProgressStage,
+    object: Option<usize>,
+    total: usize,
+    completed: AtomicUsize,
+    last_sent: Mutex<Instant>,
+    messages: MessageSink<'a>,
+}
+
+impl<'a> ProgressCounter<'a> {
+    ///Counter for the stage of the object, or of every object if None
+    pub fn new(
+        stage: ProgressStage,
+        object: Option<usize>,
+        total: usize,
+        messages: MessageSink<'a>,
+    ) -> Self {
+        let counter = ProgressCounter {
+            stage,
+            object,
+            total,
+            completed: AtomicUsize::new(0),
+            last_sent: Mutex::new(Instant::now()),
+            messages,
+        };
+        counter.send(0);
+        counter
+    }
+
+    ///Record a completed unit of work, safe to call from parallel loops
+    pub fn increment(&self) {
+        if !self.messages.is_enabled() {
+            return;
+        }
+
+        //Messages are sent while holding the lock so the counts never go backwards
+        if self.completed.fetch_add(1, Ordering::SeqCst) + 1 == self.total {
+            let _last_sent = self.last_sent.lock().expect("Progress lock poisoned");
+            self.send(self.total);
+        } else if let Ok(mut last_sent) = self.last_sent.try_lock() {
+            //Other threads skip sending while one holds the lock
+            let completed = self.completed.load(Ordering::SeqCst);
+            if completed < self.total && last_sent.elapsed() >= PROGRESS_INTERVAL {
+                *last_sent = Instant::now();
+                self.send(completed);
+            }
+        }
+    }
+
+    fn send(&self, completed: usize) {
+        self.messages.send(Message::Progress(ProgressUpdate {
+            stage: self.stage,
+            object: self.object,
+            completed,
+            total: self.total,
+        }));
+    }
+}
+
+///Flag shared with a running slice so it can be stopped early
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    ///Return the cancelled error if the slice has been cancelled
+    pub fn check(&self) -> Result<(), SlicerErrors> {
+        if self.is_cancelled() {
+            Err(SlicerErrors::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 #[inline]
 pub fn point_y_lerp(a: &Coord<f64>, b: &Coord<f64>, y: f64) -> Coord<f64> {
     Coord {
@@ -128,6 +301,7 @@ pub fn orientation(p: &Coord<f64>, q: &Coord<f64>, r: &Coord<f64>) -> Orientatio
 #[cfg(test)]
 mod tests {
     use super::*;
+    use gladius_shared::messages::ResponseMessage;
 
     #[test]
     fn basic_directional_unit_bisector() {
@@ -182,4 +356,47 @@ mod tests {
             Vector2::new(1.0, 0.0)
         );
     }
+
+    #[test]
+    fn json_messages_one_per_line() {
+        let mut output = vec![];
+
+        write_message(
+            &mut output,
+            &Message::StateUpdate("Slicing".to_string()),
+            MessageFormat::Json,
+        )
+        .expect("Vec is writable");
+        write_message(
+            &mut output,
+            &ResponseMessage {
+                id: 4,
+                message: Message::Progress(ProgressUpdate {
+                    stage: ProgressStage::Perimeters,
+                    object: Some(1),
+                    completed: 2,
+                    total: 10,
+                }),
+            },
+            MessageFormat::Json,
+        )
+        .expect("Vec is writable");
+
+        assert_eq!(
+            String::from_utf8(output).expect("JSON is utf8"),
+            "{\"StateUpdate\":\"Slicing\"}\n\
+             {\"id\":4,\"message\":{\"Progress\":{\"stage\":\"Perimeters\",\"object\":1,\"completed\":2,\"total\":10}}}\n"
+        );
+    }
+
+    #[test]
+    fn write_errors_are_returned() {
+        let message = Message::StateUpdate("Slicing".to_string());
+
+        for format in [MessageFormat::Bincode, MessageFormat::Json] {
+            let err = write_message(&mut ClosedPipe, &message, format)
+                .expect_err("Closed pipes can't be written");
+            assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+        }
+    }
 }